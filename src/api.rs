@@ -0,0 +1,350 @@
+//! Local HTTP and WebSocket control API, bound to localhost only.
+//!
+//! - `GET /params` all parameters as a JSON object of paths and values, `GET /params/<path>` one
+//! - `PUT /params` with a JSON object of paths and values, or `PUT /params/<path>` with a number
+//! - `POST /reset`, `POST /screenshot`, `POST /record/start`, `POST /record/stop`, and
+//!   `POST /record` to toggle
+//! - `GET /frame.png` the latest frame
+//! - `GET /stats` the frame stats as JSON, or as a stream of them over a WebSocket
+//!
+//! Requests that change something are queued as actions for the event loop, the same as OSC
+//! messages and keys. Reads come from a `Status` the event loop publishes every frame.
+//!
+//! Binding to localhost doesn't keep out web pages open in a browser on the same machine, so
+//! requests must name the API itself as `Host` (against DNS rebinding), mustn't come from
+//! another `Origin`, and parameter writes must be `application/json`, which a page can't send
+//! without a CORS preflight.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde::Serialize;
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+use tungstenite::protocol::Role;
+use tungstenite::WebSocket;
+
+use crate::control::{Action, Frame};
+use crate::params::SimParams;
+
+/// Time between two messages of the stats stream.
+const STATS_INTERVAL: Duration = Duration::from_millis(250);
+/// How long a frame request waits for the event loop.
+const FRAME_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Frame statistics, as shown in the HUD.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Stats {
+    pub fps: f32,
+    pub frame_time_ms: f32,
+    pub frame: u64,
+    pub seed: u64,
+    pub num_slimes: u32,
+    /// Index of the next recorded image, if recording.
+    pub recording: Option<usize>,
+    /// Frame of the timeline or audio, if one is playing.
+    pub animation_frame: Option<u64>,
+}
+
+/// State the event loop publishes every frame for the API to read.
+#[derive(Debug, Default)]
+pub struct Status {
+    pub params: SimParams,
+    pub stats: Stats,
+}
+
+pub type SharedStatus = Arc<Mutex<Status>>;
+
+/// Serves the API on `localhost:<port>` in a thread of its own. Every request gets a thread, so
+/// a WebSocket or a slow frame doesn't hold up the others.
+pub fn serve(
+    port: u16,
+    tx: Sender<Action>,
+    status: SharedStatus,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let server = Server::http(("127.0.0.1", port))?;
+    println!("Serving the control API on http://127.0.0.1:{}", port);
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let tx = tx.clone();
+            let status = status.clone();
+            thread::spawn(move || handle(request, port, &tx, &status));
+        }
+    });
+    Ok(())
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).unwrap()
+}
+
+fn json(value: &impl Serialize) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_data(serde_json::to_vec(value).unwrap())
+        .with_header(header("Content-Type", "application/json"))
+}
+
+fn error(code: u16, message: &str) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_string(message).with_status_code(code)
+}
+
+fn header_value<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv(name))
+        .map(|h| h.value.as_str())
+}
+
+/// Rejects requests that don't address the API by a local name, or that a page from another
+/// origin sent.
+fn check_origin(request: &Request, port: u16) -> Result<(), &'static str> {
+    let local = |host: &str| {
+        let host = host.to_ascii_lowercase();
+        host == format!("127.0.0.1:{}", port) || host == format!("localhost:{}", port)
+    };
+    match header_value(request, "Host") {
+        Some(host) if local(host) => {}
+        _ => return Err("Host must be 127.0.0.1 or localhost with the API's port"),
+    }
+    match header_value(request, "Origin") {
+        None => Ok(()),
+        Some(origin) if origin.strip_prefix("http://").is_some_and(local) => Ok(()),
+        Some(_) => Err("cross-origin requests are not allowed"),
+    }
+}
+
+fn is_json(request: &Request) -> bool {
+    header_value(request, "Content-Type").is_some_and(|value| {
+        value
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .eq_ignore_ascii_case("application/json")
+    })
+}
+
+fn handle(mut request: Request, port: u16, tx: &Sender<Action>, status: &SharedStatus) {
+    let url = request.url().to_owned();
+    let path = url.split('?').next().unwrap_or("");
+    let method = request.method().clone();
+    if let Err(e) = check_origin(&request, port) {
+        let _ = request.respond(error(403, e));
+        return;
+    }
+    if (method == Method::Put || method == Method::Post)
+        && (path == "/params" || path.starts_with("/params/"))
+        && !is_json(&request)
+    {
+        let _ = request.respond(error(415, "Content-Type must be application/json"));
+        return;
+    }
+    if path == "/stats" && is_websocket(&request) {
+        stream_stats(request, status);
+        return;
+    }
+    let mut body = String::new();
+    if let Err(e) = request.as_reader().read_to_string(&mut body) {
+        let _ = request.respond(error(400, &e.to_string()));
+        return;
+    }
+
+    let queue = |action| {
+        // The receiver only goes away when the window closes
+        let _ = tx.send(action);
+        Response::from_string("").with_status_code(204)
+    };
+    let response = match (&method, path) {
+        (Method::Get, "/params") => {
+            let params = status.lock().unwrap().params;
+            let values: BTreeMap<String, f32> = SimParams::paths()
+                .into_iter()
+                .filter_map(|p| Some((p.clone(), params.get(&p)?)))
+                .collect();
+            json(&values)
+        }
+        (Method::Put | Method::Post, "/params") => {
+            match serde_json::from_str::<BTreeMap<String, f32>>(&body) {
+                Ok(values) => set_params(values.into_iter().collect(), tx),
+                Err(e) => error(400, &format!("expected an object of numbers: {}", e)),
+            }
+        }
+        (Method::Get, p) if p.starts_with("/params/") => {
+            let params = status.lock().unwrap().params;
+            match params.get(&param_path(p)) {
+                Some(value) => json(&value),
+                None => error(404, "unknown parameter"),
+            }
+        }
+        (Method::Put | Method::Post, p) if p.starts_with("/params/") => {
+            match body.trim().parse::<f32>() {
+                Ok(value) => set_params(vec![(param_path(p), value)], tx),
+                Err(_) => error(400, "expected a number"),
+            }
+        }
+        (Method::Post, "/reset") => queue(Action::Reset),
+        (Method::Post, "/screenshot") => queue(Action::Screenshot),
+        (Method::Post, "/record") => queue(Action::Record(None)),
+        (Method::Post, "/record/start") => queue(Action::Record(Some(true))),
+        (Method::Post, "/record/stop") => queue(Action::Record(Some(false))),
+        (Method::Get, "/frame.png") => match latest_frame(tx) {
+            Ok(png) => Response::from_data(png).with_header(header("Content-Type", "image/png")),
+            Err(e) => error(503, &e),
+        },
+        (Method::Get, "/stats") => json(&status.lock().unwrap().stats),
+        (
+            _,
+            "/params" | "/reset" | "/screenshot" | "/record" | "/record/start" | "/record/stop"
+            | "/frame.png" | "/stats",
+        ) => error(405, "method not allowed"),
+        _ => error(404, "not found"),
+    };
+    let _ = request.respond(response);
+}
+
+/// `/params/species/0/move_speed` or `/params/species.0.move_speed` to `species.0.move_speed`.
+fn param_path(url_path: &str) -> String {
+    url_path["/params/".len()..].replace('/', ".")
+}
+
+/// Checks every path before queueing any of them, so a typo doesn't leave half a change applied.
+fn set_params(
+    values: Vec<(String, f32)>,
+    tx: &Sender<Action>,
+) -> Response<std::io::Cursor<Vec<u8>>> {
+    let unknown: Vec<&str> = values
+        .iter()
+        .filter(|(path, value)| SimParams::default().set(path, *value).is_err())
+        .map(|(path, _)| path.as_str())
+        .collect();
+    if !unknown.is_empty() {
+        return error(404, &format!("unknown parameters: {}", unknown.join(", ")));
+    }
+    for (path, value) in values {
+        let _ = tx.send(Action::SetParam(path, value));
+    }
+    Response::from_string("").with_status_code(204)
+}
+
+/// Asks the event loop for the next frame and encodes it as a PNG.
+fn latest_frame(tx: &Sender<Action>) -> Result<Vec<u8>, String> {
+    let (reply, frame) = channel();
+    tx.send(Action::CaptureFrame(reply))
+        .map_err(|_| "the simulation has stopped".to_owned())?;
+    let Frame {
+        width,
+        height,
+        rgba,
+    } = frame
+        .recv_timeout(FRAME_TIMEOUT)
+        .map_err(|_| "timed out waiting for a frame".to_owned())?;
+    let mut png = Vec::new();
+    image::codecs::png::PngEncoder::new(&mut png)
+        .encode(&rgba, width, height, image::ColorType::Rgba8)
+        .map_err(|e| e.to_string())?;
+    Ok(png)
+}
+
+fn is_websocket(request: &Request) -> bool {
+    request
+        .headers()
+        .iter()
+        .any(|h| h.field.equiv("Upgrade") && h.value.as_str().eq_ignore_ascii_case("websocket"))
+}
+
+/// Completes the WebSocket handshake and sends the stats as JSON text messages until the
+/// client goes away. `handle` has already checked the `Origin` of the upgrade.
+fn stream_stats(request: Request, status: &SharedStatus) {
+    let key = header_value(&request, "Sec-WebSocket-Key").map(str::to_owned);
+    let key = match key {
+        Some(key) => key,
+        None => {
+            let _ = request.respond(error(400, "missing Sec-WebSocket-Key"));
+            return;
+        }
+    };
+    let response = Response::empty(StatusCode(101)).with_header(header(
+        "Sec-WebSocket-Accept",
+        &tungstenite::handshake::derive_accept_key(key.as_bytes()),
+    ));
+    let stream = request.upgrade("websocket", response);
+    let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+    loop {
+        let stats = serde_json::to_string(&status.lock().unwrap().stats).unwrap();
+        if socket.send(tungstenite::Message::Text(stats)).is_err() {
+            return;
+        }
+        thread::sleep(STATS_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::sync::mpsc::Receiver;
+
+    const PORT: u16 = 38471;
+
+    fn server() -> Receiver<Action> {
+        let (tx, rx) = channel();
+        serve(PORT, tx, SharedStatus::default()).unwrap();
+        rx
+    }
+
+    /// Sends a raw request and returns the status code of the response.
+    fn status_of(request: &str) -> u16 {
+        let mut stream = TcpStream::connect(("127.0.0.1", PORT)).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response.split(' ').nth(1).unwrap().parse().unwrap()
+    }
+
+    fn put_params(headers: &str) -> u16 {
+        let body = r#"{"world.decay": 0.9}"#;
+        status_of(&format!(
+            "PUT /params HTTP/1.1\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            headers,
+            body.len(),
+            body
+        ))
+    }
+
+    #[test]
+    fn only_local_same_origin_json_writes_are_accepted() {
+        let rx = server();
+        let host = format!("Host: localhost:{}\r\n", PORT);
+        let json = "Content-Type: application/json\r\n";
+
+        assert_eq!(put_params(&format!("{}{}", host, json)), 204);
+        assert!(
+            matches!(rx.try_recv(), Ok(Action::SetParam(p, v)) if p == "world.decay" && v == 0.9)
+        );
+
+        // DNS rebinding, a foreign page and a CORS-simple content type
+        assert_eq!(
+            put_params(&format!("Host: evil.example:{}\r\n{}", PORT, json)),
+            403
+        );
+        assert_eq!(
+            put_params(&format!("{}Origin: http://evil.example\r\n{}", host, json)),
+            403
+        );
+        assert_eq!(
+            put_params(&format!("{}Content-Type: text/plain\r\n", host)),
+            415
+        );
+        let record = format!(
+            "POST /record/start HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nOrigin: null\r\n\
+             Content-Length: 0\r\nConnection: close\r\n\r\n",
+            PORT
+        );
+        assert_eq!(status_of(&record), 403);
+        assert!(rx.try_recv().is_err());
+    }
+}
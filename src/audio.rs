@@ -0,0 +1,227 @@
+//! Audio-reactive parameters. A WAV file is analysed up front with an FFT per video frame, and
+//! the energy of configurable frequency bands drives parameters through a mapping. Levels are
+//! looked up by frame number, so an offline render lines up exactly with the audio track.
+
+use std::error::Error;
+use std::path::Path;
+
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use serde::Deserialize;
+
+use crate::params::SimParams;
+
+/// Band levels are divided by this percentile of the band over the whole track, so a single
+/// loud hit doesn't leave the rest of the song dim.
+const NORMALIZE_PERCENTILE: usize = 95;
+
+/// One band driving one parameter.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Mapping {
+    /// Parameter path such as `species.0.deposit_amount`, see `SimParams::field`.
+    pub param: String,
+    /// Frequency range in Hz.
+    pub band: [f32; 2],
+    /// Parameter value at silence and at full level.
+    pub min: f32,
+    pub max: f32,
+    /// Multiplies the normalised level before it is clamped to 1.
+    #[serde(default = "default_gain")]
+    pub gain: f32,
+    /// Fraction of the previous frame's level kept, 0 follows the music exactly.
+    #[serde(default)]
+    pub smoothing: f32,
+}
+
+fn default_gain() -> f32 {
+    1.0
+}
+
+impl Mapping {
+    fn new(param: &str, band: [f32; 2], min: f32, max: f32) -> Self {
+        Mapping {
+            param: param.to_owned(),
+            band,
+            min,
+            max,
+            gain: 1.0,
+            smoothing: 0.5,
+        }
+    }
+}
+
+/// A mapping file, read from TOML. Everything is optional.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AudioConfig {
+    /// Video frames per second. The track is analysed once per frame, and the simulation steps
+    /// at this rate while the audio drives it.
+    pub fps: f64,
+    /// Samples per FFT, centred on each frame.
+    pub fft_size: usize,
+    #[serde(rename = "map")]
+    pub mappings: Vec<Mapping>,
+}
+
+impl Default for AudioConfig {
+    /// Bass drives the deposit, mids the speed, highs the turning and the whole spectrum the
+    /// palette intensity.
+    fn default() -> Self {
+        AudioConfig {
+            fps: 60.0,
+            fft_size: 2048,
+            mappings: vec![
+                Mapping::new("species.0.deposit_amount", [20.0, 150.0], 0.05, 0.4),
+                Mapping::new("species.1.deposit_amount", [20.0, 150.0], 0.05, 0.4),
+                Mapping::new("species.0.move_speed", [150.0, 2000.0], 2.0, 8.0),
+                Mapping::new("species.1.move_speed", [150.0, 2000.0], 2.0, 8.0),
+                Mapping::new("species.0.turn_speed", [2000.0, 12000.0], 0.2, 0.9),
+                Mapping::new("species.1.turn_speed", [2000.0, 12000.0], 0.2, 0.9),
+                Mapping::new("palette.brightness", [20.0, 20000.0], 0.5, 1.2),
+            ],
+        }
+    }
+}
+
+impl AudioConfig {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let config: AudioConfig = toml::from_str(&std::fs::read_to_string(path)?)?;
+        if config.fps <= 0.0 || config.fft_size < 2 {
+            return Err("fps must be positive and fft_size at least 2".into());
+        }
+        for mapping in &config.mappings {
+            SimParams::default().set(&mapping.param, mapping.min)?;
+            let [low, high] = mapping.band;
+            if !(low >= 0.0 && low < high) {
+                return Err(format!(
+                    "band of {} must be [low_hz, high_hz] with 0 <= low < high",
+                    mapping.param
+                )
+                .into());
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// A WAV file analysed into a level per mapping and frame.
+pub struct AudioReactive {
+    pub fps: f64,
+    mappings: Vec<Mapping>,
+    /// `levels[mapping][frame]`, each between 0 and 1.
+    levels: Vec<Vec<f32>>,
+    /// Video frames covering the whole track.
+    pub frames: u64,
+}
+
+/// Reads a WAV file as mono samples between -1 and 1.
+fn read_wav(path: &Path) -> Result<(Vec<f32>, u32), Box<dyn Error>> {
+    let mut reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = 1.0 / (1u64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f32 * scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+    let channels = spec.channels.max(1) as usize;
+    let mono = interleaved
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+    Ok((mono, spec.sample_rate))
+}
+
+impl AudioReactive {
+    pub fn load(wav: &Path, config: &AudioConfig) -> Result<Self, Box<dyn Error>> {
+        let (samples, sample_rate) = read_wav(wav)?;
+        if samples.is_empty() {
+            return Err(format!("{}: no samples", wav.display()).into());
+        }
+        let duration = samples.len() as f64 / sample_rate as f64;
+        let frames = (duration * config.fps).ceil() as u64;
+        let n = config.fft_size;
+        let hann: Vec<f32> = (0..n)
+            .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / n as f32).cos())
+            .collect();
+        let bin_hz = sample_rate as f32 / n as f32;
+        let bins: Vec<(usize, usize)> = config
+            .mappings
+            .iter()
+            .map(|m| {
+                // Bands above the file's Nyquist frequency end up on the last bin
+                let low = ((m.band[0] / bin_hz).floor().max(1.0) as usize).min(n / 2);
+                let high = ((m.band[1] / bin_hz).ceil() as usize).clamp(low + 1, n / 2 + 1);
+                (low, high)
+            })
+            .collect();
+
+        let fft = FftPlanner::new().plan_fft_forward(n);
+        let mut buffer = vec![Complex::default(); n];
+        let mut levels = vec![Vec::with_capacity(frames as usize); config.mappings.len()];
+        for frame in 0..frames {
+            // Window centred on the frame's time, silence past either end of the track
+            let center = (frame as f64 / config.fps * sample_rate as f64) as i64;
+            let start = center - n as i64 / 2;
+            for (i, value) in buffer.iter_mut().enumerate() {
+                let sample = usize::try_from(start + i as i64)
+                    .ok()
+                    .and_then(|s| samples.get(s))
+                    .copied()
+                    .unwrap_or(0.0);
+                *value = Complex::new(sample * hann[i], 0.0);
+            }
+            fft.process(&mut buffer);
+            for (level, &(low, high)) in levels.iter_mut().zip(&bins) {
+                let energy: f32 = buffer[low..high].iter().map(|c| c.norm_sqr()).sum();
+                level.push((energy / (high - low) as f32).sqrt());
+            }
+        }
+
+        for (level, mapping) in levels.iter_mut().zip(&config.mappings) {
+            let mut sorted = level.clone();
+            sorted.sort_by(f32::total_cmp);
+            let top = sorted
+                .get(sorted.len().saturating_sub(1) * NORMALIZE_PERCENTILE / 100)
+                .copied()
+                .unwrap_or(0.0)
+                .max(f32::EPSILON);
+            let smoothing = mapping.smoothing.clamp(0.0, 0.99);
+            let mut previous = 0.0;
+            for value in level.iter_mut() {
+                let normalized = (*value / top * mapping.gain).min(1.0);
+                previous = previous * smoothing + normalized * (1.0 - smoothing);
+                *value = previous;
+            }
+        }
+
+        Ok(AudioReactive {
+            fps: config.fps,
+            mappings: config.mappings.clone(),
+            levels,
+            frames,
+        })
+    }
+
+    /// Simulation step for one video frame.
+    pub fn delta_time(&self) -> f32 {
+        (1.0 / self.fps) as f32
+    }
+
+    /// Sets every mapped parameter for `frame`. Past the end of the track the parameters sit at
+    /// their silence values.
+    pub fn apply(&self, frame: u64, params: &mut SimParams) {
+        for (mapping, levels) in self.mappings.iter().zip(&self.levels) {
+            let level = levels.get(frame as usize).copied().unwrap_or(0.0);
+            // The paths were checked when loading
+            let _ = params.set(
+                &mapping.param,
+                mapping.min + (mapping.max - mapping.min) * level,
+            );
+        }
+    }
+}
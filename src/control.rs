@@ -0,0 +1,33 @@
+//! Actions that drive the running simulation, whether they come from the keyboard or a remote
+//! controller. They are queued on a channel and handled once per frame in the event loop.
+
+use std::sync::mpsc::{Receiver, Sender};
+
+/// A captured frame, tightly packed RGBA rows.
+#[derive(Debug)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Action {
+    /// Sets the parameter at a path such as `species.0.move_speed`, see `SimParams::field`.
+    SetParam(String, f32),
+    /// Puts the slimes back and clears the trail, like `Space`.
+    Reset,
+    /// Saves an image, like `S`.
+    Screenshot,
+    /// Starts or stops recording, toggling if `None`, like `R`.
+    Record(Option<bool>),
+    /// Attracts the slimes to the center or releases them, toggling if `None`, like `C`.
+    MoveToCenter(Option<bool>),
+    /// Sends the next frame back.
+    CaptureFrame(Sender<Frame>),
+}
+
+/// Actions queued since the last frame.
+pub fn pending(rx: &Receiver<Action>) -> Vec<Action> {
+    rx.try_iter().collect()
+}
@@ -0,0 +1,512 @@
+//! Headless parameter search. Samples species parameters within configured ranges, runs each
+//! candidate for a fixed number of frames and scores the trail it leaves with image metrics.
+//! The best candidates are saved as presets, each with a thumbnail.
+
+use std::error::Error;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use serde::Deserialize;
+
+use crate::food::{self, FoodSource};
+use crate::network;
+use crate::params::{MoveParams, SimParams, NUM_SPECIES};
+use crate::presets::{self, Preset};
+use crate::shaders::{ShaderDir, ShaderLoader};
+use crate::simulation::{self, Simulation};
+use crate::sizing::SimSize;
+
+/// Every candidate runs at a fixed 60 fps so results don't depend on how fast the GPU is.
+const DELTA_TIME: f32 = 1.0 / 60.0;
+/// The trail is averaged down to at most this width before it is measured.
+const ANALYSIS_WIDTH: usize = 256;
+/// Frequencies, in cycles per analysis pixel, that count as network structure. Lower is
+/// blobs and gradients, higher is noise.
+const STRUCTURE_BAND: (f32, f32) = (1.0 / 32.0, 1.0 / 4.0);
+/// Pixels at least this bright after normalisation count as covered.
+const COVERED: f32 = 0.1;
+
+/// Range a parameter is sampled from, written `[min, max]`.
+pub type Range = [f32; 2];
+
+/// Ranges of the species parameters.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Ranges {
+    pub move_speed: Range,
+    pub turn_speed: Range,
+    pub sensor_distance: Range,
+    pub sensor_angle: Range,
+    pub deposit_amount: Range,
+}
+
+impl Default for Ranges {
+    fn default() -> Self {
+        Ranges {
+            move_speed: [0.5, 6.0],
+            turn_speed: [0.05, 1.0],
+            sensor_distance: [2.0, 50.0],
+            sensor_angle: [0.1, 1.4],
+            deposit_amount: [0.05, 0.5],
+        }
+    }
+}
+
+impl Ranges {
+    fn sample(&self, rng: &mut impl Rng) -> MoveParams {
+        let mut sample = |[min, max]: Range| {
+            if max > min {
+                rng.gen_range(min..max)
+            } else {
+                min
+            }
+        };
+        MoveParams::new(
+            sample(self.move_speed),
+            sample(self.turn_speed),
+            sample(self.sensor_distance),
+            sample(self.sensor_angle),
+            sample(self.deposit_amount),
+        )
+    }
+}
+
+/// How much each metric counts towards the score. Relative, a zero weight ignores the metric.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ScoreWeights {
+    pub edges: f32,
+    pub coverage: f32,
+    pub structure: f32,
+    pub change: f32,
+}
+
+impl Default for ScoreWeights {
+    fn default() -> Self {
+        ScoreWeights {
+            edges: 1.0,
+            coverage: 1.0,
+            structure: 1.0,
+            change: 1.0,
+        }
+    }
+}
+
+/// Settings of a search, read from a TOML file. Everything is optional.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ExploreConfig {
+    /// Parameter sets tried.
+    pub candidates: usize,
+    /// Frames each candidate runs before it is scored.
+    pub frames: u32,
+    /// Frames between the two trail readbacks the change rate is measured from.
+    pub change_frames: u32,
+    /// Best candidates saved as presets.
+    pub keep: usize,
+    /// Seed of the parameter sampling and the swarm, random if missing.
+    pub seed: Option<u64>,
+    pub world: (u32, u32),
+    pub slimes: u32,
+    /// Species sampled. Only these get slimes, the rest keep their default parameters.
+    pub species: usize,
+    /// Width of the saved thumbnails.
+    pub thumbnail_width: u32,
+    /// Change rate, in mean normalised trail difference per frame, that scores best. Static
+    /// patterns score low, and so does flicker.
+    pub target_change: f32,
+    pub ranges: Ranges,
+    pub weights: ScoreWeights,
+}
+
+impl Default for ExploreConfig {
+    fn default() -> Self {
+        ExploreConfig {
+            candidates: 32,
+            frames: 600,
+            change_frames: 10,
+            keep: 5,
+            seed: None,
+            world: (1024, 576),
+            slimes: 256 * 1024,
+            species: 2,
+            thumbnail_width: 256,
+            target_change: 0.005,
+            ranges: Ranges::default(),
+            weights: ScoreWeights::default(),
+        }
+    }
+}
+
+impl ExploreConfig {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(toml::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+/// Image metrics of a candidate, each between 0 and 1.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Metrics {
+    /// Mean gradient magnitude of the trail.
+    pub edges: f32,
+    /// Fraction of the world covered by trail.
+    pub coverage: f32,
+    /// Fraction of the spectral energy in `STRUCTURE_BAND`.
+    pub structure: f32,
+    /// Mean change of the trail per frame, not limited to 1.
+    pub change: f32,
+}
+
+impl Metrics {
+    /// Combines the metrics with `weights`. Coverage scores best at half the world, and change
+    /// at `target_change`, the others the higher the better.
+    pub fn score(&self, weights: &ScoreWeights, target_change: f32) -> f32 {
+        let coverage = 4.0 * self.coverage * (1.0 - self.coverage);
+        let change = if self.change > 0.0 {
+            (-(self.change / target_change).ln().powi(2)).exp()
+        } else {
+            0.0
+        };
+        let total = weights.edges + weights.coverage + weights.structure + weights.change;
+        if total <= 0.0 {
+            return 0.0;
+        }
+        (weights.edges * self.edges
+            + weights.coverage * coverage
+            + weights.structure * self.structure
+            + weights.change * change)
+            / total
+    }
+}
+
+struct Candidate {
+    params: SimParams,
+    metrics: Metrics,
+    score: f32,
+    /// Packed RGBA of the final frame.
+    image: Vec<u8>,
+}
+
+/// The summed trail averaged down to `ANALYSIS_WIDTH` and scaled so the 99th percentile is 1.
+struct Trail {
+    width: usize,
+    height: usize,
+    pixels: Vec<f32>,
+}
+
+impl Trail {
+    fn new(world: &[f32], (width, height): (u32, u32)) -> Self {
+        let (width, height) = (width as usize, height as usize);
+        let full = network::total_trail(world, width * height);
+        let factor = width.div_ceil(ANALYSIS_WIDTH).max(1);
+        let (w, h) = ((width / factor).max(1), (height / factor).max(1));
+        let mut pixels = vec![0.0; w * h];
+        for y in 0..h * factor {
+            for x in 0..w * factor {
+                pixels[x / factor + y / factor * w] += full[x + y * width];
+            }
+        }
+        let mut sorted = pixels.clone();
+        sorted.sort_by(f32::total_cmp);
+        let top = sorted[(sorted.len() - 1) * 99 / 100].max(f32::EPSILON);
+        for p in &mut pixels {
+            *p = (*p / top).min(1.0);
+        }
+        Trail {
+            width: w,
+            height: h,
+            pixels,
+        }
+    }
+
+    fn coverage(&self) -> f32 {
+        self.pixels.iter().filter(|&&p| p >= COVERED).count() as f32 / self.pixels.len() as f32
+    }
+
+    /// Mean Sobel gradient magnitude, scaled so a hard step edge is 1.
+    fn edges(&self) -> f32 {
+        let (w, h) = (self.width, self.height);
+        if w < 3 || h < 3 {
+            return 0.0;
+        }
+        let p = |x: usize, y: usize| self.pixels[x + y * w];
+        let mut sum = 0.0;
+        for y in 1..h - 1 {
+            for x in 1..w - 1 {
+                let gx = p(x + 1, y - 1) + 2.0 * p(x + 1, y) + p(x + 1, y + 1)
+                    - p(x - 1, y - 1)
+                    - 2.0 * p(x - 1, y)
+                    - p(x - 1, y + 1);
+                let gy = p(x - 1, y + 1) + 2.0 * p(x, y + 1) + p(x + 1, y + 1)
+                    - p(x - 1, y - 1)
+                    - 2.0 * p(x, y - 1)
+                    - p(x + 1, y - 1);
+                sum += (gx * gx + gy * gy).sqrt() / 4.0;
+            }
+        }
+        (sum / ((w - 2) * (h - 2)) as f32).min(1.0)
+    }
+
+    /// Fraction of the power spectrum, without the mean, that falls in `STRUCTURE_BAND`.
+    fn structure(&self) -> f32 {
+        let (w, h) = (self.width, self.height);
+        let mean = self.pixels.iter().sum::<f32>() / self.pixels.len() as f32;
+        let mut data: Vec<Complex<f32>> = self
+            .pixels
+            .iter()
+            .map(|&p| Complex::new(p - mean, 0.0))
+            .collect();
+        let mut planner = FftPlanner::new();
+        let rows = planner.plan_fft_forward(w);
+        for row in data.chunks_exact_mut(w) {
+            rows.process(row);
+        }
+        let columns = planner.plan_fft_forward(h);
+        let mut column = vec![Complex::default(); h];
+        for x in 0..w {
+            for y in 0..h {
+                column[y] = data[x + y * w];
+            }
+            columns.process(&mut column);
+            for y in 0..h {
+                data[x + y * w] = column[y];
+            }
+        }
+
+        let (mut band, mut total) = (0.0, 0.0);
+        for y in 0..h {
+            let fy = y.min(h - y) as f32 / h as f32;
+            for x in 0..w {
+                let fx = x.min(w - x) as f32 / w as f32;
+                let power = data[x + y * w].norm_sqr();
+                let frequency = (fx * fx + fy * fy).sqrt();
+                total += power;
+                if (STRUCTURE_BAND.0..STRUCTURE_BAND.1).contains(&frequency) {
+                    band += power;
+                }
+            }
+        }
+        if total > 0.0 {
+            band / total
+        } else {
+            0.0
+        }
+    }
+
+    /// Mean absolute difference to `earlier`, per frame.
+    fn change(&self, earlier: &Trail, frames: u32) -> f32 {
+        let difference: f32 = self
+            .pixels
+            .iter()
+            .zip(&earlier.pixels)
+            .map(|(a, b)| (a - b).abs())
+            .sum();
+        difference / self.pixels.len() as f32 / frames.max(1) as f32
+    }
+}
+
+/// Runs the search and saves the best candidates to `presets/explore-<time>-<rank>`.
+pub fn run(
+    config: &ExploreConfig,
+    shader_dir: ShaderDir,
+    food_path: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    let size = SimSize {
+        world: config.world,
+        num_slimes: config.slimes,
+    };
+    let food_sources: Vec<FoodSource> = match food_path {
+        Some(path) => food::load(path, size.world, SimParams::default().food.radius)?,
+        None => Vec::new(),
+    };
+    let seed = config.seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64
+    });
+    let mut rng = StdRng::seed_from_u64(seed);
+    let change_frames = config.change_frames.clamp(1, config.frames.max(1));
+    let species = config.species.clamp(1, NUM_SPECIES);
+
+    let (device, queue) = pollster::block_on(simulation::headless_device(&size))?;
+    let shader_loader = ShaderLoader::new(shader_dir, &device);
+    let mut params = SimParams::default();
+    params.swarm.species_weights = std::array::from_fn(|i| if i < species { 1.0 } else { 0.0 });
+    let mut sim = Simulation::new(&device, &shader_loader, size, &params, food_sources, seed);
+
+    println!(
+        "Exploring {} candidates of {} frames, seed {}",
+        config.candidates, config.frames, seed
+    );
+    let mut candidates = Vec::with_capacity(config.candidates);
+    for index in 0..config.candidates {
+        for move_params in params.species.species.iter_mut().take(species) {
+            *move_params = config.ranges.sample(&mut rng);
+        }
+        sim.write_params(&queue, &params);
+        sim.reset(&queue);
+
+        let mut earlier = None;
+        for frame in 0..config.frames {
+            sim.step(&device, &queue, DELTA_TIME, &params);
+            if frame + change_frames == config.frames {
+                let world = network::read_world(&device, &queue, &sim.current_world_buffer, &size);
+                earlier = Some(Trail::new(&world, size.world));
+            }
+        }
+        let world = network::read_world(&device, &queue, &sim.current_world_buffer, &size);
+        let trail = Trail::new(&world, size.world);
+        let metrics = Metrics {
+            edges: trail.edges(),
+            coverage: trail.coverage(),
+            structure: trail.structure(),
+            change: earlier.map_or(0.0, |e| trail.change(&e, change_frames)),
+        };
+        let score = metrics.score(&config.weights, config.target_change);
+        println!(
+            "{:>4}/{}  score {:.3}  edges {:.3}  coverage {:.3}  structure {:.3}  change {:.5}",
+            index + 1,
+            config.candidates,
+            score,
+            metrics.edges,
+            metrics.coverage,
+            metrics.structure,
+            metrics.change
+        );
+        candidates.push(Candidate {
+            params,
+            metrics,
+            score,
+            image: sim.capture(&device, &queue),
+        });
+    }
+
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis();
+    for (rank, candidate) in candidates.iter().take(config.keep).enumerate() {
+        let name = format!("explore-{}-{}", time, rank + 1);
+        presets::save(&name, &Preset::from_params(&candidate.params))?;
+        save_thumbnail(&candidate.image, size.world, config.thumbnail_width, &name)?;
+        println!(
+            "Saved Preset: {} (score {:.3}, edges {:.3}, coverage {:.3}, structure {:.3})",
+            name,
+            candidate.score,
+            candidate.metrics.edges,
+            candidate.metrics.coverage,
+            candidate.metrics.structure
+        );
+    }
+    Ok(())
+}
+
+fn save_thumbnail(
+    rgba: &[u8],
+    (width, height): (u32, u32),
+    thumbnail_width: u32,
+    name: &str,
+) -> Result<(), Box<dyn Error>> {
+    let image = image::RgbaImage::from_raw(width, height, rgba.to_vec())
+        .ok_or("captured frame has the wrong size")?;
+    let thumbnail_width = thumbnail_width.clamp(1, width);
+    let thumbnail_height = (height as u64 * thumbnail_width as u64 / width as u64).max(1) as u32;
+    image::imageops::thumbnail(&image, thumbnail_width, thumbnail_height)
+        .save(presets::thumbnail_path(name))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: (u32, u32) = (256, 256);
+
+    /// A world with `trail(x, y)` on the first species' plane.
+    fn trail(trail: impl Fn(usize, usize) -> f32) -> Trail {
+        let (width, height) = (SIZE.0 as usize, SIZE.1 as usize);
+        let mut world = vec![0.0; width * height * NUM_SPECIES];
+        for y in 0..height {
+            for x in 0..width {
+                world[x + y * width] = trail(x, y);
+            }
+        }
+        Trail::new(&world, SIZE)
+    }
+
+    fn metrics(trail: &Trail) -> Metrics {
+        Metrics {
+            edges: trail.edges(),
+            coverage: trail.coverage(),
+            structure: trail.structure(),
+            change: 0.0,
+        }
+    }
+
+    #[test]
+    fn networks_score_above_blank_and_noise() {
+        let blank = metrics(&trail(|_, _| 0.0));
+        // Two pixel wide strands every 16 pixels
+        let network = metrics(&trail(
+            |x, y| {
+                if x % 16 < 2 || y % 16 < 2 {
+                    1.0
+                } else {
+                    0.0
+                }
+            },
+        ));
+        let mut rng = StdRng::seed_from_u64(1);
+        let values: Vec<f32> = (0..SIZE.0 * SIZE.1).map(|_| rng.gen()).collect();
+        let noise = metrics(&trail(|x, y| values[x + y * SIZE.0 as usize]));
+
+        assert_eq!(blank.edges, 0.0);
+        assert_eq!(blank.coverage, 0.0);
+        assert_eq!(blank.structure, 0.0);
+        assert!(network.edges > 0.1, "{:?}", network);
+        assert!(
+            network.coverage > 0.1 && network.coverage < 0.5,
+            "{:?}",
+            network
+        );
+        assert!(
+            network.structure > noise.structure,
+            "{:?} {:?}",
+            network,
+            noise
+        );
+
+        let weights = ScoreWeights::default();
+        let target = ExploreConfig::default().target_change;
+        let score = |m: &Metrics| m.score(&weights, target);
+        assert!(score(&blank) < score(&network));
+        assert!(score(&noise) < score(&network));
+    }
+
+    #[test]
+    fn change_peaks_at_the_target() {
+        let weights = ScoreWeights {
+            edges: 0.0,
+            coverage: 0.0,
+            structure: 0.0,
+            change: 1.0,
+        };
+        let score = |change| {
+            Metrics {
+                change,
+                ..Default::default()
+            }
+            .score(&weights, 0.005)
+        };
+        assert_eq!(score(0.0), 0.0);
+        assert!((score(0.005) - 1.0).abs() < 1e-6);
+        assert!(score(0.05) < score(0.01));
+        assert!(score(0.0005) < score(0.002));
+    }
+}
@@ -0,0 +1,236 @@
+//! Food sources loaded from a CSV or GeoJSON file of points. Each source emits chemoattractant
+//! into the trail every frame and is eaten by the slimes passing over it, see `food.wgsl`.
+
+use std::error::Error;
+use std::path::Path;
+
+use serde::Deserialize;
+
+/// A food source, mirrored by `FoodSource` in `food.wgsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct FoodSource {
+    pub pos: [f32; 2],
+    /// Nutrients left, the source stops emitting when it reaches zero.
+    pub nutrients: f32,
+    /// Nutrients it started with. Emission scales with how full the source still is.
+    pub capacity: f32,
+}
+
+/// Fraction of the world left empty around the food sources on each side.
+const MARGIN: f64 = 0.1;
+
+/// A row of a food CSV. `nutrients` is optional and defaults to 1.
+#[derive(Deserialize)]
+struct CsvRow {
+    x: f64,
+    y: f64,
+    nutrients: Option<f64>,
+}
+
+/// A point read from a file, in the file's coordinates.
+struct Point {
+    x: f64,
+    y: f64,
+    nutrients: f64,
+}
+
+/// Loads food sources from `path` and fits them into a world of `world_size`, keeping their
+/// aspect ratio. CSV files need `x` and `y` columns, with `y` pointing down like the world.
+/// GeoJSON files are read as longitude and latitude and projected equirectangular at their mean
+/// latitude, north ends up at the top. Both take an optional `nutrients` value per point.
+/// Sources that would overlap at `radius` are merged into one, see `merge_overlapping`.
+pub fn load(
+    path: &Path,
+    world_size: (u32, u32),
+    radius: i32,
+) -> Result<Vec<FoodSource>, Box<dyn Error>> {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let (points, flip_y) = match extension.to_ascii_lowercase().as_str() {
+        "csv" => (read_csv(path)?, false),
+        "geojson" | "json" => (read_geojson(path)?, true),
+        _ => return Err(format!("{}: expected a .csv or .geojson file", path.display()).into()),
+    };
+    if points.is_empty() {
+        return Err(format!("{}: no food sources found", path.display()).into());
+    }
+    let sources = fit_to_world(&points, world_size, flip_y);
+    Ok(merge_overlapping(sources, radius))
+}
+
+/// Merges sources whose discs could share a pixel into one at their nutrient weighted centre,
+/// holding all their nutrients. `food.wgsl` updates the trail around each source without
+/// atomics, so overlapping sources would lose each other's emission and consumption in a
+/// different way every frame. Raising the radius past half the spacing at runtime brings that
+/// back.
+fn merge_overlapping(sources: Vec<FoodSource>, radius: i32) -> Vec<FoodSource> {
+    // Centres are truncated to whole pixels, which can bring them up to a pixel closer per axis
+    let min_distance = 2.0 * radius as f32 + 2.0;
+    let mut merged: Vec<FoodSource> = Vec::with_capacity(sources.len());
+    for source in sources {
+        let overlapping = merged.iter_mut().find(|m| {
+            let (dx, dy) = (m.pos[0] - source.pos[0], m.pos[1] - source.pos[1]);
+            dx * dx + dy * dy <= min_distance * min_distance
+        });
+        match overlapping {
+            Some(m) => {
+                let total = m.capacity + source.capacity;
+                if total > 0.0 {
+                    for axis in 0..2 {
+                        m.pos[axis] =
+                            (m.pos[axis] * m.capacity + source.pos[axis] * source.capacity) / total;
+                    }
+                }
+                m.nutrients += source.nutrients;
+                m.capacity = total;
+            }
+            None => merged.push(source),
+        }
+    }
+    merged
+}
+
+fn read_csv(path: &Path) -> Result<Vec<Point>, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(path)?;
+    let mut points = Vec::new();
+    for row in reader.deserialize() {
+        let row: CsvRow = row?;
+        points.push(Point {
+            x: row.x,
+            y: row.y,
+            nutrients: row.nutrients.unwrap_or(1.0),
+        });
+    }
+    Ok(points)
+}
+
+fn read_geojson(path: &Path) -> Result<Vec<Point>, Box<dyn Error>> {
+    let geojson: geojson::GeoJson = std::fs::read_to_string(path)?.parse()?;
+    let features = match geojson {
+        geojson::GeoJson::FeatureCollection(collection) => collection.features,
+        geojson::GeoJson::Feature(feature) => vec![feature],
+        geojson::GeoJson::Geometry(geometry) => vec![geojson::Feature {
+            geometry: Some(geometry),
+            ..Default::default()
+        }],
+    };
+    let mut points = Vec::new();
+    for feature in &features {
+        let nutrients = feature
+            .property("nutrients")
+            .and_then(|n| n.as_f64())
+            .unwrap_or(1.0);
+        let positions = match feature.geometry.as_ref().map(|g| &g.value) {
+            Some(geojson::Value::Point(position)) => vec![position],
+            Some(geojson::Value::MultiPoint(positions)) => positions.iter().collect(),
+            // Lines and polygons aren't food, skip them so annotated maps still load
+            _ => continue,
+        };
+        points.extend(positions.into_iter().map(|p| Point {
+            x: p[0],
+            y: p[1],
+            nutrients,
+        }));
+    }
+    project_equirectangular(&mut points);
+    Ok(points)
+}
+
+/// Projects longitude and latitude onto a plane, equirectangular at the points' mean latitude.
+/// A degree of longitude shrinks with the cosine of the latitude, at Tokyo's 35.7° unscaled
+/// degrees would stretch the map east-west by a factor of 1.23.
+fn project_equirectangular(points: &mut [Point]) {
+    if points.is_empty() {
+        return;
+    }
+    let mean_latitude = points.iter().map(|p| p.y).sum::<f64>() / points.len() as f64;
+    let scale = mean_latitude.to_radians().cos();
+    for point in points {
+        point.x *= scale;
+    }
+}
+
+fn fit_to_world(points: &[Point], world_size: (u32, u32), flip_y: bool) -> Vec<FoodSource> {
+    let min_x = points.iter().map(|p| p.x).fold(f64::INFINITY, f64::min);
+    let max_x = points.iter().map(|p| p.x).fold(f64::NEG_INFINITY, f64::max);
+    let min_y = points.iter().map(|p| p.y).fold(f64::INFINITY, f64::min);
+    let max_y = points.iter().map(|p| p.y).fold(f64::NEG_INFINITY, f64::max);
+
+    let (width, height) = (world_size.0 as f64, world_size.1 as f64);
+    let usable = (width * (1.0 - 2.0 * MARGIN), height * (1.0 - 2.0 * MARGIN));
+    let span = (
+        (max_x - min_x).max(f64::EPSILON),
+        (max_y - min_y).max(f64::EPSILON),
+    );
+    let scale = (usable.0 / span.0).min(usable.1 / span.1);
+    // Center the points, a single point or a line of them ends up in the middle
+    let offset = (
+        (width - (max_x - min_x) * scale) / 2.0,
+        (height - (max_y - min_y) * scale) / 2.0,
+    );
+
+    points
+        .iter()
+        .map(|p| {
+            let y = if flip_y { max_y - p.y } else { p.y - min_y };
+            FoodSource {
+                pos: [
+                    ((p.x - min_x) * scale + offset.0) as f32,
+                    (y * scale + offset.1) as f32,
+                ],
+                nutrients: p.nutrients as f32,
+                capacity: p.nutrients as f32,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: f64, y: f64) -> Point {
+        Point {
+            x,
+            y,
+            nutrients: 1.0,
+        }
+    }
+
+    #[test]
+    fn geojson_degrees_are_projected_at_the_mean_latitude() {
+        // Two points a degree of latitude apart and one a degree of longitude east, at 60°N
+        let mut points = vec![point(0.0, 59.5), point(0.0, 60.5), point(1.0, 60.0)];
+        project_equirectangular(&mut points);
+        let sources = fit_to_world(&points, (1000, 1000), true);
+        let north_south = (sources[1].pos[1] - sources[0].pos[1]).abs();
+        let east_west = (sources[2].pos[0] - sources[0].pos[0]).abs();
+        assert!(
+            (north_south / east_west - 2.0).abs() < 1e-3,
+            "{} {}",
+            north_south,
+            east_west
+        );
+        // North is up
+        assert!(sources[1].pos[1] < sources[0].pos[1]);
+    }
+
+    #[test]
+    fn overlapping_sources_are_merged() {
+        let source = |x: f32, nutrients: f32| FoodSource {
+            pos: [x, 0.0],
+            nutrients,
+            capacity: nutrients,
+        };
+        let merged = merge_overlapping(
+            vec![source(0.0, 1.0), source(5.0, 3.0), source(50.0, 1.0)],
+            4,
+        );
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].nutrients, 4.0);
+        assert_eq!(merged[0].pos[0], 3.75);
+        assert_eq!(merged[1].pos[0], 50.0);
+    }
+}
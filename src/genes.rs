@@ -0,0 +1,104 @@
+//! Per-slime movement genes. With genetics enabled each slime moves by its own genes instead of
+//! its species' parameters, and slimes born from a split inherit their parent's genes with some
+//! mutation, see `lifecycle.wgsl`.
+
+use crate::params::{MoveParams, NUM_SPECIES};
+use crate::Slime;
+
+/// Slimes sampled for `GeneStats`, a sample is enough and keeps the readback cheap.
+pub const STATS_SAMPLE: u32 = 64 * 1024;
+
+/// Mirrored by `Genes` in `slimes.wgsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Genes {
+    pub move_speed: f32,
+    pub turn_speed: f32,
+    pub sensor_distance: f32,
+    pub sensor_angle: f32,
+}
+
+impl Genes {
+    pub const NAMES: [&'static str; 4] = ["speed", "turn", "sense", "angle"];
+
+    pub fn from_params(params: &MoveParams) -> Self {
+        Genes {
+            move_speed: params.move_speed,
+            turn_speed: params.turn_speed,
+            sensor_distance: params.sensor_distance,
+            sensor_angle: params.sensor_angle,
+        }
+    }
+
+    fn values(&self) -> [f32; 4] {
+        [
+            self.move_speed,
+            self.turn_speed,
+            self.sensor_distance,
+            self.sensor_angle,
+        ]
+    }
+}
+
+/// Distribution of one gene within a species.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GeneSummary {
+    pub mean: f32,
+    pub std_dev: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+/// Gene distributions of the living slimes of each species.
+#[derive(Debug, Clone, Default)]
+pub struct GeneStats {
+    /// Slimes the statistics were taken from, per species.
+    pub counts: [usize; NUM_SPECIES],
+    pub genes: [[GeneSummary; 4]; NUM_SPECIES],
+}
+
+impl GeneStats {
+    pub fn new(slimes: &[Slime]) -> Self {
+        let mut stats = GeneStats::default();
+        let mut sums = [[0.0f64; 4]; NUM_SPECIES];
+        let mut squares = [[0.0f64; 4]; NUM_SPECIES];
+        for species in &mut stats.genes {
+            for gene in species {
+                gene.min = f32::INFINITY;
+                gene.max = f32::NEG_INFINITY;
+            }
+        }
+        for slime in slimes.iter().filter(|s| s.energy > 0.0) {
+            let s = slime.species as usize;
+            stats.counts[s] += 1;
+            for (g, value) in slime.genes.values().into_iter().enumerate() {
+                sums[s][g] += value as f64;
+                squares[s][g] += value as f64 * value as f64;
+                let gene = &mut stats.genes[s][g];
+                gene.min = gene.min.min(value);
+                gene.max = gene.max.max(value);
+            }
+        }
+        for s in 0..NUM_SPECIES {
+            let n = stats.counts[s] as f64;
+            for g in 0..4 {
+                let gene = &mut stats.genes[s][g];
+                if n == 0.0 {
+                    *gene = GeneSummary::default();
+                    continue;
+                }
+                let mean = sums[s][g] / n;
+                gene.mean = mean as f32;
+                gene.std_dev = (squares[s][g] / n - mean * mean).max(0.0).sqrt() as f32;
+            }
+        }
+        stats
+    }
+}
+
+/// Gives every slime the genes of its species' current parameters.
+pub fn reset(slimes: &mut [Slime], species: &[MoveParams; NUM_SPECIES]) {
+    for slime in slimes {
+        slime.genes = Genes::from_params(&species[slime.species as usize]);
+    }
+}
@@ -0,0 +1,291 @@
+use crate::params::SimParams;
+use crate::presets;
+
+pub enum PanelAction {
+    SavePreset(String),
+    LoadPreset(String),
+    Screenshot,
+    SetSlimeCount(u32),
+    RefillFood,
+    ExportNetwork,
+    /// Give every slime the genes of its species' current parameters.
+    ResetGenes,
+    /// Reassign the species of every existing slime from the swarm weights.
+    RebalanceSpecies,
+}
+
+#[derive(Default)]
+pub struct PanelResponse {
+    pub species_changed: bool,
+    pub interactions_changed: bool,
+    pub palette_changed: bool,
+    pub lifecycle_changed: bool,
+    pub genetics_changed: bool,
+    pub action: Option<PanelAction>,
+}
+
+/// Window with sliders for every tunable parameter.
+pub struct ParamPanel {
+    open: bool,
+    preset_name: String,
+    presets: Vec<String>,
+    /// Slime count being edited, `None` shows the current count.
+    slime_count: Option<u32>,
+}
+
+impl ParamPanel {
+    pub fn new() -> Self {
+        ParamPanel {
+            open: false,
+            preset_name: String::from("untitled"),
+            presets: presets::list(),
+            slime_count: None,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        if self.open {
+            self.presets = presets::list();
+        }
+    }
+
+    pub fn show(
+        &mut self,
+        ctx: &egui::CtxRef,
+        params: &mut SimParams,
+        num_slimes: u32,
+    ) -> PanelResponse {
+        let SimParams {
+            species,
+            interactions,
+            world,
+            palette,
+            swarm,
+            lifecycle,
+            genetics,
+            food,
+            network,
+        } = params;
+        let mut response = PanelResponse::default();
+        let mut open = self.open;
+        egui::Window::new("Parameters")
+            .open(&mut open)
+            .anchor(egui::Align2::RIGHT_TOP, [-8.0, 8.0])
+            .default_width(280.0)
+            .vscroll(true)
+            .show(ctx, |ui| {
+                for (i, p) in species.species.iter_mut().enumerate() {
+                    ui.collapsing(format!("Species {}", i), |ui| {
+                        let mut changed = false;
+                        changed |= ui
+                            .add(egui::Slider::new(&mut p.move_speed, 0.0..=20.0).text("speed"))
+                            .changed();
+                        changed |= ui
+                            .add(egui::Slider::new(&mut p.turn_speed, 0.0..=3.2).text("turn speed"))
+                            .changed();
+                        changed |= ui
+                            .add(
+                                egui::Slider::new(&mut p.sensor_distance, 0.0..=100.0)
+                                    .text("sensor distance"),
+                            )
+                            .changed();
+                        changed |= ui
+                            .add(
+                                egui::Slider::new(&mut p.sensor_angle, 0.0..=3.2)
+                                    .text("sensor angle"),
+                            )
+                            .changed();
+                        changed |= ui
+                            .add(
+                                egui::Slider::new(&mut p.deposit_amount, 0.0..=1.0).text("deposit"),
+                            )
+                            .changed();
+                        response.species_changed |= changed;
+                    });
+                }
+
+                ui.collapsing("Interactions", |ui| {
+                    ui.label("How strongly each species (row) follows each trail (column)");
+                    egui::Grid::new("interactions").show(ui, |ui| {
+                        ui.label("");
+                        for j in 0..interactions.weights.len() {
+                            ui.label(j.to_string());
+                        }
+                        ui.end_row();
+                        for (i, row) in interactions.weights.iter_mut().enumerate() {
+                            ui.label(i.to_string());
+                            for weight in row.iter_mut() {
+                                response.interactions_changed |= ui
+                                    .add(
+                                        egui::DragValue::new(weight)
+                                            .speed(0.01)
+                                            .clamp_range(-2.0..=2.0),
+                                    )
+                                    .changed();
+                            }
+                            ui.end_row();
+                        }
+                    });
+                });
+
+                ui.collapsing("Slimes", |ui| {
+                    ui.horizontal(|ui| {
+                        let count = self.slime_count.get_or_insert(num_slimes);
+                        ui.add(
+                            egui::DragValue::new(count)
+                                .speed(1024.0)
+                                .clamp_range(1..=u32::MAX),
+                        );
+                        if ui.button("Apply").clicked() {
+                            response.action = Some(PanelAction::SetSlimeCount(*count));
+                        }
+                        if *count == num_slimes {
+                            self.slime_count = None;
+                        }
+                    });
+                    for (i, weight) in swarm.species_weights.iter_mut().enumerate() {
+                        ui.add(egui::Slider::new(weight, 0.0..=1.0).text(format!("species {}", i)));
+                    }
+                    if ui.button("Rebalance").clicked() {
+                        response.action = Some(PanelAction::RebalanceSpecies);
+                    }
+                });
+
+                ui.collapsing("Lifecycle", |ui| {
+                    let mut changed = false;
+                    let mut enabled = lifecycle.enabled != 0;
+                    if ui.checkbox(&mut enabled, "enabled").changed() {
+                        lifecycle.enabled = enabled as u32;
+                        changed = true;
+                    }
+                    changed |= ui
+                        .add(
+                            egui::Slider::new(&mut lifecycle.drain, 0.0..=0.05)
+                                .logarithmic(true)
+                                .text("drain"),
+                        )
+                        .changed();
+                    changed |= ui
+                        .add(egui::Slider::new(&mut lifecycle.gain, 0.0..=1.0).text("gain"))
+                        .changed();
+                    changed |= ui
+                        .add(
+                            egui::Slider::new(&mut lifecycle.split_energy, 0.5..=1.0)
+                                .text("split energy"),
+                        )
+                        .changed();
+                    response.lifecycle_changed |= changed;
+                });
+
+                ui.collapsing("Genetics", |ui| {
+                    let mut enabled = genetics.enabled != 0;
+                    let mut changed = ui
+                        .checkbox(&mut enabled, "move by each slime's genes")
+                        .changed();
+                    genetics.enabled = enabled as u32;
+                    changed |= ui
+                        .add(egui::Slider::new(&mut genetics.mutation, 0.0..=0.5).text("mutation"))
+                        .changed();
+                    response.genetics_changed |= changed;
+                    if ui.button("Reset genes to species").clicked() {
+                        response.action = Some(PanelAction::ResetGenes);
+                    }
+                });
+
+                ui.collapsing("Food", |ui| {
+                    ui.add(
+                        egui::Slider::new(&mut food.emission, 0.0..=1.0)
+                            .logarithmic(true)
+                            .text("emission"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut food.consumption, 0.0..=0.1)
+                            .logarithmic(true)
+                            .text("consumption"),
+                    );
+                    ui.add(egui::Slider::new(&mut food.radius, 0..=32).text("radius"));
+                    if ui.button("Refill").clicked() {
+                        response.action = Some(PanelAction::RefillFood);
+                    }
+                });
+
+                ui.collapsing("Network", |ui| {
+                    ui.add(
+                        egui::Slider::new(&mut network.threshold, 0.01..=10.0)
+                            .logarithmic(true)
+                            .text("threshold"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut network.min_spur_length, 0.0..=100.0)
+                            .text("min spur length"),
+                    );
+                    if ui.button("Export").clicked() {
+                        response.action = Some(PanelAction::ExportNetwork);
+                    }
+                });
+
+                ui.collapsing("Trail", |ui| {
+                    ui.add(egui::Slider::new(&mut world.decay, 0.5..=1.0).text("decay"));
+                    ui.add(egui::Slider::new(&mut world.blur_radius, 0..=4).text("blur radius"));
+                    ui.add(
+                        egui::Slider::new(&mut world.cutoff, 0.0..=0.01)
+                            .logarithmic(true)
+                            .text("cutoff"),
+                    );
+                });
+
+                ui.collapsing("Palette", |ui| {
+                    let mut changed = false;
+                    for (i, tint) in palette.tints.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            let mut rgb = [tint[0], tint[1], tint[2]];
+                            if ui.color_edit_button_rgb(&mut rgb).changed() {
+                                tint[..3].copy_from_slice(&rgb);
+                                changed = true;
+                            }
+                            changed |= ui
+                                .add(
+                                    egui::Slider::new(&mut tint[3], 0.0..=4.0)
+                                        .text(format!("species {}", i)),
+                                )
+                                .changed();
+                        });
+                    }
+                    changed |= ui
+                        .add(
+                            egui::Slider::new(&mut palette.brightness, 0.0..=4.0)
+                                .text("brightness"),
+                        )
+                        .changed();
+                    response.palette_changed |= changed;
+                });
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.preset_name);
+                    if ui.button("Save").clicked() && !self.preset_name.is_empty() {
+                        response.action = Some(PanelAction::SavePreset(self.preset_name.clone()));
+                        if !self.presets.contains(&self.preset_name) {
+                            self.presets.push(self.preset_name.clone());
+                            self.presets.sort();
+                        }
+                    }
+                });
+                ui.horizontal_wrapped(|ui| {
+                    for name in &self.presets {
+                        if ui.button(name).clicked() {
+                            self.preset_name = name.clone();
+                            response.action = Some(PanelAction::LoadPreset(name.clone()));
+                        }
+                    }
+                });
+                ui.separator();
+                if ui.button("Screenshot").clicked() {
+                    response.action = Some(PanelAction::Screenshot);
+                }
+            });
+        self.open = open;
+        response
+    }
+}
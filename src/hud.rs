@@ -0,0 +1,132 @@
+use std::time::Duration;
+
+use crate::genes::{GeneStats, Genes};
+use crate::params::SpeciesParams;
+use crate::present::Filter;
+use crate::shaders::ShaderErrors;
+
+/// Exponential moving average of the frame time, so the readout doesn't flicker.
+#[derive(Default)]
+pub struct FrameStats {
+    frame_time: f32,
+}
+
+impl FrameStats {
+    pub fn tick(&mut self, delta_time: Duration) {
+        let dt = delta_time.as_secs_f32();
+        if self.frame_time == 0.0 {
+            self.frame_time = dt;
+        } else {
+            self.frame_time = self.frame_time * 0.95 + dt * 0.05;
+        }
+    }
+
+    pub fn fps(&self) -> f32 {
+        if self.frame_time > 0.0 {
+            1.0 / self.frame_time
+        } else {
+            0.0
+        }
+    }
+
+    pub fn frame_time_ms(&self) -> f32 {
+        self.frame_time * 1000.0
+    }
+}
+
+pub struct HudInfo<'a> {
+    pub stats: &'a FrameStats,
+    pub frame: u128,
+    /// Index of the next recorded image, if recording.
+    pub recording: Option<usize>,
+    pub seed: u64,
+    /// Current frame and length of the timeline or audio, if one is playing.
+    pub animation: Option<(u64, u64)>,
+    /// How the world is scaled to the window.
+    pub filter: Filter,
+    pub num_slimes: u32,
+    pub species: &'a SpeciesParams,
+    /// Gene distributions, while genetics is enabled.
+    pub genes: Option<&'a GeneStats>,
+}
+
+pub fn draw(ctx: &egui::CtxRef, info: &HudInfo) {
+    egui::Area::new("hud")
+        .anchor(egui::Align2::LEFT_TOP, [8.0, 8.0])
+        .interactable(false)
+        .show(ctx, |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.monospace(format!(
+                    "{:6.1} fps  {:6.2} ms",
+                    info.stats.fps(),
+                    info.stats.frame_time_ms()
+                ));
+                ui.monospace(format!("frame    {}", info.frame));
+                match info.recording {
+                    Some(index) => {
+                        ui.colored_label(egui::Color32::RED, format!("recording  image {}", index))
+                    }
+                    None => ui.monospace("recording  off"),
+                };
+                ui.monospace(format!("seed     {}", info.seed));
+                if let Some((frame, length)) = info.animation {
+                    ui.monospace(format!("animation {} / {}", frame.min(length), length));
+                }
+                ui.monospace(format!("filter   {}", info.filter));
+                ui.monospace(format!("slimes   {}", info.num_slimes));
+                ui.separator();
+                ui.monospace("   speed  turn  sense  angle  deposit");
+                for (i, p) in info.species.species.iter().enumerate() {
+                    ui.monospace(format!(
+                        "{} {:6.2} {:5.2} {:6.1} {:6.2} {:8.3}",
+                        i,
+                        p.move_speed,
+                        p.turn_speed,
+                        p.sensor_distance,
+                        p.sensor_angle,
+                        p.deposit_amount
+                    ));
+                }
+                if let Some(stats) = info.genes {
+                    ui.separator();
+                    ui.monospace("genes    mean    std     min     max");
+                    for (i, genes) in stats.genes.iter().enumerate() {
+                        if stats.counts[i] == 0 {
+                            continue;
+                        }
+                        ui.monospace(format!("species {} ({} sampled)", i, stats.counts[i]));
+                        for (name, g) in Genes::NAMES.iter().zip(genes) {
+                            ui.monospace(format!(
+                                "{:6} {:7.2} {:6.2} {:7.2} {:7.2}",
+                                name, g.mean, g.std_dev, g.min, g.max
+                            ));
+                        }
+                    }
+                }
+            });
+        });
+}
+
+/// Shader reload failures, drawn regardless of whether the HUD is shown so they can't be missed.
+pub fn draw_shader_errors(ctx: &egui::CtxRef, errors: &ShaderErrors) {
+    if errors.is_empty() {
+        return;
+    }
+    egui::Area::new("shader_errors")
+        .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -8.0])
+        .interactable(false)
+        .show(ctx, |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                for (shader, error) in errors.iter() {
+                    ui.colored_label(
+                        egui::Color32::from_rgb(255, 96, 96),
+                        format!(
+                            "{} failed to load, still running the last good version",
+                            shader
+                        ),
+                    );
+                    ui.monospace(error.to_string());
+                }
+            });
+        });
+}
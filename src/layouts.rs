@@ -0,0 +1,339 @@
+//! Bind group layouts shared by the Rust side and the shaders. Each binding records the Rust
+//! type uploaded to it, so `reflect` can check the shader declares the same layout.
+
+use wgpu::ShaderStages;
+
+use crate::food::FoodSource;
+use crate::genes::Genes;
+use crate::params::{GeneticsParams, LifecycleParams, MoveParams, Palette, SpeciesParams};
+use crate::present::PresentConfig;
+use crate::{
+    FoodConfig, Slime, SlimeMoveConfig, StaticGlobalParams, WorldUpdateConfig, WorldView,
+    TEXTURE_FORMAT,
+};
+
+/// Size and field offsets of a Rust type that is copied into a GPU buffer.
+pub struct HostLayout {
+    pub name: &'static str,
+    pub size: usize,
+    /// Fields in declaration order. Padding fields (starting with `_`) are left out.
+    pub fields: &'static [HostField],
+}
+
+pub struct HostField {
+    pub name: &'static str,
+    pub offset: usize,
+    pub size: usize,
+    /// Layout of the field's type, or of its element for arrays, when that is a struct too.
+    pub inner: Option<&'static HostLayout>,
+}
+
+const fn field_size<T, F>(_: fn(&T) -> &F) -> usize {
+    std::mem::size_of::<F>()
+}
+
+/// Describes a `#[repr(C)]` struct, `host_layout!(Type { field, nested => NESTED_LAYOUT })`.
+macro_rules! host_layout {
+    ($ty:ty) => {
+        HostLayout {
+            name: stringify!($ty),
+            size: std::mem::size_of::<$ty>(),
+            fields: &[],
+        }
+    };
+    ($ty:ty { $($field:ident $(=> $inner:expr)?),* $(,)? }) => {
+        HostLayout {
+            name: stringify!($ty),
+            size: std::mem::size_of::<$ty>(),
+            fields: &[$(HostField {
+                name: stringify!($field),
+                offset: std::mem::offset_of!($ty, $field),
+                size: field_size(|v: &$ty| &v.$field),
+                inner: host_layout!(@inner $($inner)?),
+            }),*],
+        }
+    };
+    (@inner) => { None };
+    (@inner $inner:expr) => { Some(&$inner) };
+}
+
+pub const GENES: HostLayout = host_layout!(Genes {
+    move_speed,
+    turn_speed,
+    sensor_distance,
+    sensor_angle,
+});
+pub const SLIME: HostLayout = host_layout!(Slime {
+    pos,
+    heading,
+    species,
+    energy,
+    genes => GENES,
+});
+/// One species' trail at one pixel, see `World` in `common.wgsl`.
+pub const TRAIL: HostLayout = host_layout!(f32);
+pub const SLOT: HostLayout = host_layout!(u32);
+/// One entry of `Interactions`, which the shader reads as a flat array.
+pub const WEIGHT: HostLayout = host_layout!(f32);
+pub const MOVE_PARAMS: HostLayout = host_layout!(MoveParams {
+    move_speed,
+    turn_speed,
+    sensor_distance,
+    sensor_angle,
+    deposit_amount,
+});
+pub const SPECIES_PARAMS: HostLayout = host_layout!(SpeciesParams {
+    species => MOVE_PARAMS
+});
+pub const SLIME_MOVE_CONFIG: HostLayout = host_layout!(SlimeMoveConfig {
+    delta_time,
+    random,
+    move_to_center,
+});
+pub const STATIC_GLOBAL_PARAMS: HostLayout = host_layout!(StaticGlobalParams {
+    width,
+    height,
+    num_slimes,
+    slimes_per_row,
+});
+pub const WORLD_UPDATE_CONFIG: HostLayout = host_layout!(WorldUpdateConfig {
+    delta_time,
+    decay,
+    blur_radius,
+    cutoff,
+});
+pub const PALETTE: HostLayout = host_layout!(Palette { tints, brightness });
+pub const WORLD_VIEW: HostLayout = host_layout!(WorldView { origin, size });
+pub const PRESENT_CONFIG: HostLayout = host_layout!(PresentConfig { filter });
+pub const FOOD_SOURCE: HostLayout = host_layout!(FoodSource {
+    pos,
+    nutrients,
+    capacity,
+});
+pub const FOOD_CONFIG: HostLayout = host_layout!(FoodConfig {
+    emission,
+    consumption,
+    radius,
+    num_sources,
+});
+pub const GENETICS_PARAMS: HostLayout = host_layout!(GeneticsParams { enabled, mutation });
+pub const LIFECYCLE_PARAMS: HostLayout = host_layout!(LifecycleParams {
+    enabled,
+    drain,
+    gain,
+    split_energy,
+});
+
+/// What is bound at a binding slot.
+pub enum Resource {
+    Uniform(&'static HostLayout),
+    /// A storage buffer holding a runtime sized array of `element`.
+    Storage {
+        read_only: bool,
+        element: &'static HostLayout,
+    },
+    StorageTexture(wgpu::TextureFormat),
+    Texture,
+    /// A sampler, which may only blend texels if `filtering`.
+    Sampler {
+        filtering: bool,
+    },
+}
+
+pub struct Binding {
+    pub binding: u32,
+    pub visibility: ShaderStages,
+    pub resource: Resource,
+}
+
+pub struct GroupLayout {
+    pub name: &'static str,
+    pub bindings: &'static [Binding],
+}
+
+/// The bind groups of a pipeline, indexed by group number.
+pub type Interface = &'static [&'static GroupLayout];
+
+const fn compute(binding: u32, resource: Resource) -> Binding {
+    Binding {
+        binding,
+        visibility: ShaderStages::COMPUTE,
+        resource,
+    }
+}
+
+pub const SLIME_MOVE: GroupLayout = GroupLayout {
+    name: "slime move",
+    bindings: &[
+        compute(0, Resource::Uniform(&SLIME_MOVE_CONFIG)),
+        compute(
+            1,
+            Resource::Storage {
+                read_only: false,
+                element: &SLIME,
+            },
+        ),
+        compute(2, Resource::Uniform(&SPECIES_PARAMS)),
+        // The free list, an `i32` count followed by the slots
+        compute(
+            3,
+            Resource::Storage {
+                read_only: false,
+                element: &SLOT,
+            },
+        ),
+        compute(4, Resource::Uniform(&LIFECYCLE_PARAMS)),
+        compute(
+            5,
+            Resource::Storage {
+                read_only: true,
+                element: &WEIGHT,
+            },
+        ),
+        compute(6, Resource::Uniform(&GENETICS_PARAMS)),
+    ],
+};
+
+pub const WORLD: GroupLayout = GroupLayout {
+    name: "world",
+    bindings: &[
+        compute(
+            0,
+            Resource::Storage {
+                read_only: true,
+                element: &TRAIL,
+            },
+        ),
+        compute(
+            1,
+            Resource::Storage {
+                read_only: false,
+                element: &TRAIL,
+            },
+        ),
+    ],
+};
+
+pub const STATIC: GroupLayout = GroupLayout {
+    name: "static params",
+    bindings: &[compute(0, Resource::Uniform(&STATIC_GLOBAL_PARAMS))],
+};
+
+pub const WORLD_UPDATE: GroupLayout = GroupLayout {
+    name: "world update",
+    bindings: &[compute(0, Resource::Uniform(&WORLD_UPDATE_CONFIG))],
+};
+
+pub const BUF_TO_TEX: GroupLayout = GroupLayout {
+    name: "buffer to texture",
+    bindings: &[
+        compute(0, Resource::StorageTexture(TEXTURE_FORMAT)),
+        compute(1, Resource::Uniform(&PALETTE)),
+        compute(2, Resource::Uniform(&WORLD_VIEW)),
+    ],
+};
+
+pub const FOOD: GroupLayout = GroupLayout {
+    name: "food",
+    bindings: &[
+        compute(0, Resource::Uniform(&FOOD_CONFIG)),
+        compute(
+            1,
+            Resource::Storage {
+                read_only: false,
+                element: &FOOD_SOURCE,
+            },
+        ),
+    ],
+};
+
+pub const COPY: GroupLayout = GroupLayout {
+    name: "copy",
+    bindings: &[
+        Binding {
+            binding: 0,
+            visibility: ShaderStages::FRAGMENT,
+            resource: Resource::Texture,
+        },
+        Binding {
+            binding: 1,
+            visibility: ShaderStages::FRAGMENT,
+            resource: Resource::Sampler { filtering: false },
+        },
+        Binding {
+            binding: 2,
+            visibility: ShaderStages::FRAGMENT,
+            resource: Resource::Sampler { filtering: true },
+        },
+        Binding {
+            binding: 3,
+            visibility: ShaderStages::FRAGMENT,
+            resource: Resource::Uniform(&PRESENT_CONFIG),
+        },
+    ],
+};
+
+pub const DOWNSAMPLE: GroupLayout = GroupLayout {
+    name: "downsample",
+    bindings: &[
+        compute(0, Resource::Texture),
+        compute(1, Resource::StorageTexture(TEXTURE_FORMAT)),
+    ],
+};
+
+pub const MOVE_SLIMES: Interface = &[&SLIME_MOVE, &WORLD, &STATIC];
+pub const LIFECYCLE: Interface = MOVE_SLIMES;
+pub const FOOD_SOURCES: Interface = &[&FOOD, &WORLD, &STATIC];
+pub const UPDATE_WORLD: Interface = &[&WORLD_UPDATE, &WORLD, &STATIC];
+pub const WORLD_TO_TEX: Interface = &[&BUF_TO_TEX, &WORLD, &STATIC];
+pub const RENDER: Interface = &[&COPY];
+pub const DOWNSAMPLE_MIPS: Interface = &[&DOWNSAMPLE];
+
+impl GroupLayout {
+    pub fn create(&self, device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let entries: Vec<_> = self
+            .bindings
+            .iter()
+            .map(|b| wgpu::BindGroupLayoutEntry {
+                binding: b.binding,
+                visibility: b.visibility,
+                ty: b.resource.binding_type(),
+                count: None,
+            })
+            .collect();
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(self.name),
+            entries: &entries,
+        })
+    }
+}
+
+impl Resource {
+    fn binding_type(&self) -> wgpu::BindingType {
+        let buffer = |ty| wgpu::BindingType::Buffer {
+            ty,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        };
+        match *self {
+            Resource::Uniform(_) => buffer(wgpu::BufferBindingType::Uniform),
+            Resource::Storage { read_only, .. } => {
+                buffer(wgpu::BufferBindingType::Storage { read_only })
+            }
+            Resource::StorageTexture(format) => wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            Resource::Texture => wgpu::BindingType::Texture {
+                multisampled: false,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            Resource::Sampler { filtering } => wgpu::BindingType::Sampler {
+                filtering,
+                comparison: false,
+            },
+        }
+    }
+}
@@ -1,11 +1,12 @@
-use std::num::NonZeroU32;
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::mpsc::channel;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use notify::{watcher, RecursiveMode, Watcher};
 use rand::Rng;
 use wgpu::util::DeviceExt;
-use wgpu::{BufferUsages, ComputePipeline, Extent3d, Features, TextureUsages};
+use wgpu::BufferUsages;
 
 use winit::event::{ElementState, KeyboardInput, VirtualKeyCode};
 use winit::{
@@ -16,7 +17,50 @@ use winit::{
 use workerpool::thunk::{Thunk, ThunkWorker};
 use workerpool::Pool;
 
-const NUM_SLIMES: u32 = 1024 * 1024 * 3; // 1024 * 1024 * 2 is MAX. Computer will crash after that
+mod api;
+mod audio;
+mod control;
+mod explore;
+mod food;
+mod genes;
+mod gui;
+mod hud;
+mod layouts;
+mod network;
+mod osc;
+mod overlay;
+mod params;
+mod present;
+mod presets;
+mod reflect;
+mod render;
+mod shaders;
+mod simulation;
+mod sizing;
+mod swarm;
+mod tiled;
+mod timeline;
+mod wallpaper;
+
+use api::SharedStatus;
+use audio::{AudioConfig, AudioReactive};
+use control::{Action, Frame};
+use explore::ExploreConfig;
+use food::FoodSource;
+use genes::{GeneStats, Genes};
+use gui::{PanelAction, ParamPanel};
+use hud::{FrameStats, HudInfo};
+use overlay::Overlay;
+use params::{FoodParams, NetworkParams, SimParams, SpeciesParams, WorldParams, NUM_SPECIES};
+use present::{Filter, MipChain, PresentConfig};
+use presets::Preset;
+use shaders::{changed_shaders, reload, ShaderDir, ShaderErrors, ShaderLoader};
+use simulation::Simulation;
+use sizing::SimSize;
+use swarm::SampleReader;
+use timeline::{Animation, Timeline};
+
+const NUM_SLIMES: u32 = 1024 * 1024 * 3; // Checked against the device limits at startup, see sizing.rs
 #[allow(dead_code)]
 const WINDOW_SIZE: (u32, u32) = ((2560.0 * 0.6) as u32, (1440.0 * 0.6) as u32);
 // const WINDOW_SIZE: (u32, u32) = ((1280.0 * 0.6) as u32, (1440.0 * 0.6) as u32);
@@ -24,9 +68,29 @@ const WINDOW_SIZE: (u32, u32) = ((2560.0 * 0.6) as u32, (1440.0 * 0.6) as u32);
 // const WORLD_SIZE: (u32, u32) = (1280, 2776); // My Phone (1284 x 2778)
 const WORLD_SIZE: (u32, u32) = ((2560.0 * 1.5) as u32, (1440.0 * 1.5) as u32); // My Monitor
                                                                                // const WORLD_SIZE: (u32, u32) = ((1280.0 * 1.5) as u32, (1440.0 * 1.5) as u32); // My Monitor
-const FLOATS_PER_PIXEL: u32 = 4;
 const VID_N_SKIP_FRAMES: u128 = 6;
 const BEGIN_WITH_RECORDING: bool = false;
+/// Frames between gene statistics readbacks while genetics is enabled and the HUD is shown.
+const GENE_STATS_INTERVAL: u128 = 30;
+/// Slimes added or removed by the `+` and `-` keys.
+const SLIME_STEP: u32 = 256 * 1024;
+
+const MOVE_SLIMES_SHADER: &str = "move_slimes.wgsl";
+const LIFECYCLE_SHADER: &str = "lifecycle.wgsl";
+const FOOD_SHADER: &str = "food.wgsl";
+const UPDATE_WORLD_SHADER: &str = "update_world.wgsl";
+const WORLD_TO_TEX_SHADER: &str = "world_to_tex.wgsl";
+const RENDER_SHADER: &str = "shader.wgsl";
+const DOWNSAMPLE_SHADER: &str = "downsample.wgsl";
+const SHADERS: [&str; 7] = [
+    MOVE_SLIMES_SHADER,
+    LIFECYCLE_SHADER,
+    FOOD_SHADER,
+    UPDATE_WORLD_SHADER,
+    WORLD_TO_TEX_SHADER,
+    RENDER_SHADER,
+    DOWNSAMPLE_SHADER,
+];
 
 const TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
 
@@ -36,6 +100,10 @@ struct Slime {
     pos: [f32; 2],
     heading: f32,
     species: u32,
+    /// Only used by the lifecycle model, slimes at zero are dead.
+    energy: f32,
+    genes: Genes,
+    _pad: f32,
 }
 
 #[repr(C)]
@@ -44,6 +112,18 @@ struct StaticGlobalParams {
     width: u32,
     height: u32,
     num_slimes: u32,
+    slimes_per_row: u32,
+}
+
+impl StaticGlobalParams {
+    fn new(size: &SimSize) -> Self {
+        StaticGlobalParams {
+            width: size.world.0,
+            height: size.world.1,
+            num_slimes: size.num_slimes,
+            slimes_per_row: size.slimes_per_row(),
+        }
+    }
 }
 
 #[repr(C)]
@@ -58,6 +138,58 @@ struct SlimeMoveConfig {
 #[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 struct WorldUpdateConfig {
     delta_time: f32,
+    decay: f32,
+    blur_radius: i32,
+    cutoff: f32,
+}
+
+impl WorldUpdateConfig {
+    fn new(delta_time: f32, world: &WorldParams) -> Self {
+        WorldUpdateConfig {
+            delta_time,
+            decay: world.decay,
+            blur_radius: world.blur_radius,
+            cutoff: world.cutoff,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct FoodConfig {
+    emission: f32,
+    consumption: f32,
+    radius: i32,
+    num_sources: u32,
+}
+
+impl FoodConfig {
+    fn new(food: &FoodParams, num_sources: usize) -> Self {
+        FoodConfig {
+            emission: food.emission,
+            consumption: food.consumption,
+            radius: food.radius,
+            num_sources: num_sources as u32,
+        }
+    }
+}
+
+/// Where the world sits in the picture it is part of, for the colour gradient of
+/// `world_to_tex.wgsl`. The world itself, except for the tiles of a tiled render.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct WorldView {
+    origin: [f32; 2],
+    size: [f32; 2],
+}
+
+impl WorldView {
+    fn new(origin: (f32, f32), size: (u32, u32)) -> Self {
+        WorldView {
+            origin: [origin.0, origin.1],
+            size: [size.0 as f32, size.1 as f32],
+        }
+    }
 }
 
 enum RecordingState {
@@ -66,34 +198,61 @@ enum RecordingState {
 }
 
 impl Slime {
-    fn new_swarm(size: usize) -> Vec<Slime> {
+    fn new_swarm(
+        size: usize,
+        world: (u32, u32),
+        species_weights: &[f32; NUM_SPECIES],
+        species_params: &SpeciesParams,
+        rng: &mut impl Rng,
+    ) -> Vec<Slime> {
+        let species = swarm::species_distribution(species_weights);
         let mut swarm = Vec::with_capacity(size);
-        let mut rng = rand::thread_rng();
         for _ in 0..size {
             let r = rng.gen_range(0.0..10.0);
             let angle = rng.gen_range(0.0..std::f32::consts::PI * 2.0);
             #[allow(unused)]
             let in_circle = [
-                angle.cos() * r + (WORLD_SIZE.0 / 2) as f32,
-                angle.sin() * r + (WORLD_SIZE.1 / 2) as f32,
+                angle.cos() * r + (world.0 / 2) as f32,
+                angle.sin() * r + (world.1 / 2) as f32,
             ];
             #[allow(unused)]
             let in_world = [
-                rng.gen_range(0.0..WORLD_SIZE.0 as f32),
-                rng.gen_range(0.0..WORLD_SIZE.1 as f32),
+                rng.gen_range(0.0..world.0 as f32),
+                rng.gen_range(0.0..world.1 as f32),
             ];
+            let species = species.as_ref().map_or(0, |s| rng.sample(s));
             swarm.push(Slime {
                 pos: in_circle,
                 heading: rng.gen_range(0.0..std::f32::consts::PI * 2.0),
                 // heading: 0.0,
-                species: rng.gen_range(0..2),
+                species: species as u32,
+                energy: 0.5,
+                genes: Genes::from_params(&species_params.species[species]),
+                _pad: 0.0,
             });
         }
         swarm
     }
 }
 
-async fn run(event_loop: EventLoop<()>, window: Window) {
+/// Options only the window has.
+struct WindowOptions {
+    osc_address: Option<SocketAddr>,
+    http_port: Option<u16>,
+    /// Filter the world is scaled to the window with, `F` cycles through them.
+    filter: Filter,
+    /// Width screenshots are scaled down to with `filter`, full size if missing.
+    screenshot_width: Option<u32>,
+}
+
+async fn run(
+    event_loop: EventLoop<()>,
+    window: Window,
+    shader_dir: ShaderDir,
+    food_sources: Vec<FoodSource>,
+    animation: Animation,
+    options: WindowOptions,
+) {
     // ============ Adapter, Device and Surface Creation ============== //
 
     let instance = wgpu::Instance::new(wgpu::Backends::PRIMARY);
@@ -107,19 +266,17 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
         .await
         .expect("error finding adapter");
 
-    let adapter_features = adapter.features();
-
-    let (device, queue) = adapter
-        .request_device(
-            &wgpu::DeviceDescriptor {
-                label: None,
-                features: adapter_features | Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
-                limits: Default::default(),
-            },
-            None,
-        )
+    let adapter_name = adapter.get_info().name;
+    let sim_size = SimSize {
+        world: WORLD_SIZE,
+        num_slimes: NUM_SLIMES,
+    };
+    let (device, queue) = simulation::request_device(&adapter, &sim_size)
         .await
-        .expect("error creating device");
+        .unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        });
     let size = window.inner_size();
 
     let format = surface.get_preferred_format(&adapter).unwrap();
@@ -132,106 +289,46 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
     };
     surface.configure(&device, &sc);
 
+    let mut overlay = Overlay::new(&device, &window, format);
+    let shader_loader = ShaderLoader::new(shader_dir, &device);
+    let mut shader_errors = ShaderErrors::default();
+
     // ============ Create Render Pipeline ============== //
 
     // We use a render pipeline just to copy the output buffer of the compute shader to the
     // swapchain. It would be nice if we could skip this, but swapchains with storage usage
     // are not fully portable.
-    let copy_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
-        label: None,
-        source: wgpu::ShaderSource::Wgsl(include_str!("shaders/shader.wgsl").into()),
-    });
-    let copy_bind_group_layout =
-        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: None,
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        multisampled: false,
-                        // Should filterable be false if we want nearest-neighbor?
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler {
-                        filtering: false,
-                        comparison: false,
-                    },
-                    count: None,
-                },
-            ],
-        });
+    let copy_bind_group_layout = layouts::COPY.create(&device);
     let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: None,
         bind_group_layouts: &[&copy_bind_group_layout],
         push_constant_ranges: &[],
     });
-    let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: None,
-        layout: Some(&pipeline_layout),
-        vertex: wgpu::VertexState {
-            module: &copy_shader,
-            entry_point: "vs_main",
-            buffers: &[],
-        },
-        fragment: Some(wgpu::FragmentState {
-            module: &copy_shader,
-            entry_point: "fs_main",
-            targets: &[format.into()],
-        }),
-        primitive: wgpu::PrimitiveState::default(),
-        depth_stencil: None,
-        multisample: wgpu::MultisampleState::default(),
-    });
-
-    // Buffer to copy the render texture to output to file
-    let world_texture_copy_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-        label: None,
-        size: (WORLD_SIZE.0 * WORLD_SIZE.1 * 4) as u64,
-        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
-        mapped_at_creation: false,
-    });
-
-    // Create two buffers for the world data, and a texture to render, (custom swapchain)
-    let init_world_data = vec![0.0f32; (WORLD_SIZE.0 * WORLD_SIZE.1 * FLOATS_PER_PIXEL) as usize];
-
-    let world_texture = device.create_texture(&wgpu::TextureDescriptor {
-        label: None,
-        size: Extent3d {
-            width: WORLD_SIZE.0,
-            height: WORLD_SIZE.1,
-            depth_or_array_layers: 1,
-        },
-        mip_level_count: 1,
-        sample_count: 1,
-        dimension: wgpu::TextureDimension::D2,
-        format: TEXTURE_FORMAT,
-        usage: TextureUsages::TEXTURE_BINDING
-            | TextureUsages::COPY_DST
-            | TextureUsages::COPY_SRC
-            | TextureUsages::STORAGE_BINDING,
-    });
-    let world_texture_view = world_texture.create_view(&Default::default());
+    let mut render_pipeline = shader_loader
+        .load_render_pipeline(
+            RENDER_SHADER,
+            &device,
+            &pipeline_layout,
+            format,
+            layouts::RENDER,
+        )
+        .unwrap_or_else(|e| panic!("{}", e));
 
-    let current_world_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: None,
-        contents: bytemuck::cast_slice(&init_world_data),
-        usage: wgpu::BufferUsages::COPY_SRC
-            | wgpu::BufferUsages::COPY_DST
-            | wgpu::BufferUsages::STORAGE,
-    });
+    // ============ Simulation ============== //
 
-    let next_world_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: None,
-        contents: bytemuck::cast_slice(&init_world_data),
-        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
-    });
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+    let mut params = SimParams::default();
+    let mut sim = Simulation::new(
+        &device,
+        &shader_loader,
+        sim_size,
+        &params,
+        food_sources,
+        seed,
+    );
 
     let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
         address_mode_u: wgpu::AddressMode::ClampToEdge,
@@ -242,328 +339,103 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
         mipmap_filter: wgpu::FilterMode::Nearest,
         ..Default::default()
     });
-    let copy_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: None,
-        layout: &copy_bind_group_layout,
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureView(&world_texture_view),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: wgpu::BindingResource::Sampler(&sampler),
-            },
-        ],
-    });
-
-    // === World Map Swap Chain === //
-    let world_bind_group_layout =
-        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: None,
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-            ],
-        });
-
-    let world_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: None,
-        layout: &world_bind_group_layout,
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: current_world_buffer.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: next_world_buffer.as_entire_binding(),
-            },
-        ],
-    });
-    let inverted_world_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: None,
-        layout: &world_bind_group_layout,
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: next_world_buffer.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: current_world_buffer.as_entire_binding(),
-            },
-        ],
-    });
-
-    // ========== Static Global Params Bind Group ====== //
-    let static_params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: None,
-        usage: wgpu::BufferUsages::UNIFORM,
-        contents: bytemuck::cast_slice(&[StaticGlobalParams {
-            width: WORLD_SIZE.0,
-            height: WORLD_SIZE.1,
-            num_slimes: NUM_SLIMES,
-        }]),
-    });
-
-    let static_params_bind_group_layout =
-        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: None,
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                count: None,
-                visibility: wgpu::ShaderStages::COMPUTE,
-                ty: wgpu::BindingType::Buffer {
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                    ty: wgpu::BufferBindingType::Uniform,
-                },
-            }],
-        });
-
-    let static_params_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: None,
-        layout: &&static_params_bind_group_layout,
-        entries: &[wgpu::BindGroupEntry {
-            binding: 0,
-            resource: static_params_buffer.as_entire_binding(),
-        }],
-    });
-
-    // ========== Slime Movement Shader ========== //
-
-    let slime_move_params = SlimeMoveConfig {
-        delta_time: 0.0,
-        random: 0.0,
-        move_to_center: 0,
-    };
-
-    let slimes = Slime::new_swarm(NUM_SLIMES as usize);
-
-    let slimes_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: None,
-        usage: BufferUsages::COPY_DST | BufferUsages::STORAGE,
-        contents: bytemuck::cast_slice(&slimes),
+    let linear_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
     });
-
-    let slime_move_params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+    let mut filter = options.filter;
+    let present_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         label: None,
-        usage: BufferUsages::COPY_DST | BufferUsages::STORAGE | BufferUsages::UNIFORM,
-        contents: bytemuck::cast_slice(&[slime_move_params]),
+        usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+        contents: bytemuck::cast_slice(&[PresentConfig::new(filter)]),
     });
-
-    let slime_move_bind_group_layout =
-        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: None,
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-            ],
-        });
-
-    let slime_move_compute_pipeline_layout =
-        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: None,
-            bind_group_layouts: &[
-                &slime_move_bind_group_layout,
-                &world_bind_group_layout,
-                &static_params_bind_group_layout,
-            ],
-            push_constant_ranges: &[],
-        });
-    let mut slime_move_pipeline = load_pipeline(
-        "src/shaders/move_slimes.wgsl",
-        &device,
-        &slime_move_compute_pipeline_layout,
-    )
-    .unwrap();
-    let slime_move_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+    let mut mip_chain = MipChain::new(&device, &shader_loader, &sim.world_texture, &sim.size);
+    let copy_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
         label: None,
-        layout: &slime_move_bind_group_layout,
+        layout: &copy_bind_group_layout,
         entries: &[
             wgpu::BindGroupEntry {
                 binding: 0,
-                resource: slime_move_params_buffer.as_entire_binding(),
+                resource: wgpu::BindingResource::TextureView(&sim.world_texture_view),
             },
             wgpu::BindGroupEntry {
                 binding: 1,
-                resource: slimes_buffer.as_entire_binding(),
+                resource: wgpu::BindingResource::Sampler(&sampler),
             },
-        ],
-    });
-
-    // ========== World Processing Shader ============ //
-    let world_update_params = WorldUpdateConfig { delta_time: 0.0 };
-
-    let world_update_params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: None,
-        usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
-        contents: bytemuck::cast_slice(&[world_update_params]),
-    });
-
-    let world_update_bind_group_layout =
-        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: None,
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::COMPUTE,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            }],
-        });
-    let world_update_compute_pipeline_layout =
-        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: None,
-            bind_group_layouts: &[
-                &world_update_bind_group_layout,
-                &world_bind_group_layout,
-                &static_params_bind_group_layout,
-            ],
-            push_constant_ranges: &[],
-        });
-    let mut world_update_pipeline = load_pipeline(
-        "src/shaders/update_world.wgsl",
-        &device,
-        &world_update_compute_pipeline_layout,
-    )
-    .unwrap();
-
-    let world_update_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: None,
-        layout: &world_update_bind_group_layout,
-        entries: &[wgpu::BindGroupEntry {
-            binding: 0,
-            resource: world_update_params_buffer.as_entire_binding(),
-        }],
-    });
-
-    // ================== BUFFER TO TEXTURE COMPUTE SHADER ================== //
-
-    let buf_to_tex_bind_group_layout =
-        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: None,
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::StorageTexture {
-                        access: wgpu::StorageTextureAccess::WriteOnly,
-                        format: TEXTURE_FORMAT,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                    },
-                    count: None,
-                },
-            ],
-        });
-    let buf_to_tex_compute_pipeline_layout =
-        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: None,
-            bind_group_layouts: &[
-                &buf_to_tex_bind_group_layout,
-                &static_params_bind_group_layout,
-                &slime_move_bind_group_layout,
-            ],
-            push_constant_ranges: &[],
-        });
-    let mut buf_to_tex_pipeline = load_pipeline(
-        "src/shaders/world_to_tex.wgsl",
-        &device,
-        &buf_to_tex_compute_pipeline_layout,
-    )
-    .unwrap();
-    let buf_to_tex_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: None,
-        layout: &buf_to_tex_bind_group_layout,
-        entries: &[
             wgpu::BindGroupEntry {
-                binding: 0,
-                resource: next_world_buffer.as_entire_binding(),
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(&linear_sampler),
             },
             wgpu::BindGroupEntry {
-                binding: 1,
-                resource: wgpu::BindingResource::TextureView(&world_texture_view),
+                binding: 3,
+                resource: present_buffer.as_entire_binding(),
             },
         ],
     });
 
     // let start_time = std::time::Instant::now();
     let mut last_frame_time = std::time::Instant::now();
-    let mut rng = rand::thread_rng();
+    let mut frame_stats = FrameStats::default();
+    let mut show_hud = true;
+    let mut param_panel = ParamPanel::new();
 
-    // Watch files in dir:
+    // Watch the shader directory, if loading from disk:
 
     let (file_tx, file_rx) = channel();
     let mut watcher = watcher(file_tx, Duration::from_millis(200)).unwrap();
-    watcher
-        .watch("./src/shaders/", RecursiveMode::Recursive)
-        .unwrap();
+    if let Some(dir) = shader_loader.dir.watch_path() {
+        watcher
+            .watch(dir, RecursiveMode::Recursive)
+            .unwrap_or_else(|e| panic!("error watching {}: {}", dir.display(), e));
+    }
 
     let save_img_pool = Pool::<ThunkWorker<()>>::new(128);
 
+    // Keys with a remote equivalent go through the same queue as OSC messages
+    let (control_tx, control_rx) = channel();
+    if let Some(address) = options.osc_address {
+        osc::listen(address, control_tx.clone()).unwrap_or_else(|e| {
+            eprintln!("error listening for OSC on {}: {}", address, e);
+            std::process::exit(1);
+        });
+    }
+    // Only published when the API is running
+    let api_status = options.http_port.map(|port| {
+        let status = SharedStatus::default();
+        api::serve(port, control_tx.clone(), status.clone()).unwrap_or_else(|e| {
+            eprintln!("error serving the API on port {}: {}", port, e);
+            std::process::exit(1);
+        });
+        status
+    });
+
     let mut recording = if BEGIN_WITH_RECORDING {
         start_recording()
     } else {
         RecordingState::Off
     };
     let mut frame_counter: u128 = 0;
+    let mut gene_stats: Option<GeneStats> = None;
+    let mut gene_sample = SampleReader::new(genes::STATS_SAMPLE);
 
     let mut moving_to_center = 0;
+    let mut requested_slimes: Option<u32> = None;
+    // Frames since the animation started, it restarts with the simulation
+    let mut animation_frame: u64 = 0;
 
     event_loop.run(move |event, _, control_flow| {
         // TODO: this may be excessive polling. It really should be synchronized with
         // swapchain presentation, but that's currently underbaked in wgpu.
         *control_flow = ControlFlow::Poll;
+        overlay.handle_event(&event);
+        if overlay.captures_event(&event) {
+            return;
+        }
         match event {
             Event::RedrawRequested(_) => {
                 let frame = surface
@@ -572,64 +444,35 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
 
                 let delta_time = last_frame_time.elapsed();
                 last_frame_time = std::time::Instant::now();
+                frame_stats.tick(delta_time);
 
-                // ----- Update Uniforms ----- //
-                queue.write_buffer(
-                    &slime_move_params_buffer,
-                    0,
-                    bytemuck::cast_slice(&[SlimeMoveConfig {
-                        delta_time: delta_time.as_secs_f32(),
-                        random: rng.gen_range(0.0..1.0),
-                        move_to_center: moving_to_center,
-                    }]),
-                );
-                queue.write_buffer(
-                    &world_update_params_buffer,
-                    0,
-                    bytemuck::cast_slice(&[WorldUpdateConfig {
-                        delta_time: delta_time.as_secs_f32(),
-                    }]),
-                );
+                // ----- Timeline and Audio ----- //
+                // Animations step at their own frame rate so playback and recordings match
+                if animation.apply(animation_frame, &mut params) {
+                    sim.write_params(&queue, &params);
+                    animation_frame += 1;
+                }
+                let sim_delta_time = animation.delta_time().unwrap_or(delta_time.as_secs_f32());
 
                 // ----- Run Compute Pipelines ----- //
                 let mut encoder = device.create_command_encoder(&Default::default());
-                {
-                    let mut cpass = encoder.begin_compute_pass(&Default::default());
-                    cpass.set_pipeline(&slime_move_pipeline);
-                    cpass.set_bind_group(0, &slime_move_bind_group, &[]);
-                    cpass.set_bind_group(1, &world_bind_group, &[]);
-                    cpass.set_bind_group(2, &static_params_bind_group, &[]);
-                    cpass.dispatch(NUM_SLIMES / 64, 1, 1);
-                }
-                {
-                    let mut cpass = encoder.begin_compute_pass(&Default::default());
-                    cpass.set_pipeline(&world_update_pipeline);
-                    cpass.set_bind_group(0, &world_update_bind_group, &[]);
-                    cpass.set_bind_group(1, &inverted_world_bind_group, &[]);
-                    cpass.set_bind_group(2, &static_params_bind_group, &[]);
-                    cpass.dispatch(WORLD_SIZE.0 / 8, WORLD_SIZE.1 / 8, 1);
-                }
-                {
-                    let mut cpass = encoder.begin_compute_pass(&Default::default());
-                    cpass.set_pipeline(&buf_to_tex_pipeline);
-                    cpass.set_bind_group(0, &buf_to_tex_bind_group, &[]);
-                    cpass.set_bind_group(1, &static_params_bind_group, &[]);
-                    cpass.set_bind_group(2, &slime_move_bind_group, &[]);
-                    cpass.dispatch(WORLD_SIZE.0 / 8, WORLD_SIZE.1 / 8, 1);
-                }
-                encoder.copy_buffer_to_buffer(
-                    &current_world_buffer,
-                    0,
-                    &next_world_buffer,
-                    0,
-                    (WORLD_SIZE.0 * WORLD_SIZE.1 * 4 * FLOATS_PER_PIXEL).into(),
+                sim.encode_frame(
+                    &queue,
+                    &mut encoder,
+                    sim_delta_time,
+                    &params,
+                    moving_to_center,
                 );
 
+                if filter.uses_mips() {
+                    mip_chain.encode(&mut encoder);
+                }
+
                 // ----- Render to Screen ----- //
+                let view = frame
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
                 {
-                    let view = frame
-                        .texture
-                        .create_view(&wgpu::TextureViewDescriptor::default());
                     let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                         label: None,
                         color_attachments: &[wgpu::RenderPassColorAttachment {
@@ -646,67 +489,182 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                     rpass.set_bind_group(0, &copy_bind_group, &[]);
                     rpass.draw(0..3, 0..2);
                 }
+
+                // ----- Overlay ----- //
+                let ctx = overlay.begin_frame();
+                if params.genetics.enabled == 0 {
+                    gene_stats = None;
+                } else if let Some(sample) = gene_sample.poll(&device) {
+                    gene_stats = Some(GeneStats::new(&sample));
+                } else if show_hud && frame_counter.is_multiple_of(GENE_STATS_INTERVAL) {
+                    gene_sample.copy(
+                        &device,
+                        &mut encoder,
+                        &sim.slimes_buffer,
+                        sim.size.num_slimes,
+                    );
+                }
+                if show_hud {
+                    hud::draw(
+                        &ctx,
+                        &HudInfo {
+                            stats: &frame_stats,
+                            frame: frame_counter,
+                            recording: match recording {
+                                RecordingState::On(_, f_index) => Some(f_index),
+                                RecordingState::Off => None,
+                            },
+                            seed,
+                            animation: animation.length().map(|length| (animation_frame, length)),
+                            filter,
+                            num_slimes: sim.size.num_slimes,
+                            species: &params.species,
+                            genes: gene_stats.as_ref(),
+                        },
+                    );
+                }
+                hud::draw_shader_errors(&ctx, &shader_errors);
+                let panel = param_panel.show(&ctx, &mut params, sim.size.num_slimes);
+                overlay.render(&device, &queue, &mut encoder, &view, &window);
                 queue.submit(Some(encoder.finish()));
+                gene_sample.map();
+
+                if panel.species_changed
+                    || panel.interactions_changed
+                    || panel.palette_changed
+                    || panel.genetics_changed
+                    || panel.lifecycle_changed
+                {
+                    sim.write_params(&queue, &params);
+                }
+                match panel.action {
+                    Some(PanelAction::SavePreset(name)) => {
+                        match presets::save(&name, &Preset::from_params(&params)) {
+                            Ok(()) => println!("Saved Preset: {}", name),
+                            Err(e) => eprintln!("Error saving preset {}: {}", name, e),
+                        }
+                    }
+                    Some(PanelAction::LoadPreset(name)) => match presets::load(&name) {
+                        Ok(preset) => {
+                            preset.apply(&mut params);
+                            sim.write_params(&queue, &params);
+                            println!("Loaded Preset: {}", name);
+                        }
+                        Err(e) => eprintln!("Error loading preset {}: {}", name, e),
+                    },
+                    Some(PanelAction::Screenshot) => save_screenshot(
+                        &device,
+                        &queue,
+                        &sim,
+                        &save_img_pool,
+                        options.screenshot_width.map(|width| (filter, width)),
+                    ),
+                    Some(PanelAction::SetSlimeCount(count)) => requested_slimes = Some(count),
+                    Some(PanelAction::ExportNetwork) => {
+                        save_network(&device, &queue, &sim, params.network, &save_img_pool)
+                    }
+                    Some(PanelAction::RefillFood) => sim.refill_food(&queue),
+                    Some(PanelAction::ResetGenes) => sim.reset_genes(&device, &queue, &params),
+                    Some(PanelAction::RebalanceSpecies) => {
+                        sim.rebalance_species(&device, &queue, &params.swarm.species_weights)
+                    }
+                    None => (),
+                }
+                for action in control::pending(&control_rx) {
+                    match action {
+                        Action::SetParam(path, value) => match params.set(&path, value) {
+                            Ok(()) => sim.write_params(&queue, &params),
+                            Err(e) => eprintln!("{}", e),
+                        },
+                        Action::Reset => {
+                            sim.reset(&queue);
+                            animation_frame = 0;
+                        }
+                        Action::Screenshot => save_screenshot(
+                            &device,
+                            &queue,
+                            &sim,
+                            &save_img_pool,
+                            options.screenshot_width.map(|width| (filter, width)),
+                        ),
+                        Action::Record(on) => {
+                            let is_recording = matches!(recording, RecordingState::On(_, _));
+                            if on.unwrap_or(!is_recording) != is_recording {
+                                recording = match recording {
+                                    RecordingState::Off => start_recording(),
+                                    RecordingState::On(_, _) => RecordingState::Off,
+                                };
+                            }
+                        }
+                        Action::MoveToCenter(on) => {
+                            moving_to_center = match on {
+                                Some(on) => on as u32,
+                                None => (moving_to_center + 1) % 2,
+                            }
+                        }
+                        Action::CaptureFrame(reply) => {
+                            let _ = reply.send(Frame {
+                                width: sim.size.world.0,
+                                height: sim.size.world.1,
+                                rgba: sim.capture(&device, &queue),
+                            });
+                        }
+                    }
+                }
+                if let Some(status) = &api_status {
+                    let mut status = status.lock().unwrap();
+                    status.params = params;
+                    status.stats = api::Stats {
+                        fps: frame_stats.fps(),
+                        frame_time_ms: frame_stats.frame_time_ms(),
+                        frame: frame_counter as u64,
+                        seed,
+                        num_slimes: sim.size.num_slimes,
+                        recording: match recording {
+                            RecordingState::On(_, f_index) => Some(f_index),
+                            RecordingState::Off => None,
+                        },
+                        animation_frame: animation.length().map(|_| animation_frame),
+                    };
+                }
                 frame.present();
+
+                if let Some(count) = requested_slimes.take() {
+                    match sim.set_slime_count(&device, &queue, &adapter_name, count, &params) {
+                        Ok(()) => println!("Slimes: {}", sim.size.num_slimes),
+                        Err(e) => eprintln!("Can't change the slime count: {}", e),
+                    }
+                }
                 frame_counter += 1;
 
-                if frame_counter % VID_N_SKIP_FRAMES == 0 {
+                if frame_counter.is_multiple_of(VID_N_SKIP_FRAMES) {
                     recording = match recording {
                         RecordingState::On(time, f_index) => {
                             let filepath = format!("videos/video-{}/image-{}.png", time, f_index);
-                            save_image(
-                                &device,
-                                &world_texture,
-                                &world_texture_copy_buffer,
-                                &queue,
-                                &save_img_pool,
-                                filepath,
-                            );
+                            save_image(&device, &queue, &sim, &save_img_pool, filepath);
                             RecordingState::On(time, f_index + 1)
                         }
                         RecordingState::Off => RecordingState::Off,
                     };
                 }
 
-                if let Ok(update) = file_rx.try_recv() {
-                    match update {
-                        notify::DebouncedEvent::Write(path) => {
-                            match path.file_name().unwrap().to_str().unwrap() {
-                                name @ "move_slimes.wgsl" => {
-                                    if let Some(p) = load_pipeline(
-                                        "src/shaders/move_slimes.wgsl",
-                                        &device,
-                                        &slime_move_compute_pipeline_layout,
-                                    ) {
-                                        slime_move_pipeline = p;
-                                        println!("Reloaded Shader: {}", name);
-                                    }
-                                }
-                                name @ "update_world.wgsl" => {
-                                    if let Some(p) = load_pipeline(
-                                        "src/shaders/update_world.wgsl",
-                                        &device,
-                                        &world_update_compute_pipeline_layout,
-                                    ) {
-                                        world_update_pipeline = p;
-                                        println!("Reloaded Shader: {}", name);
-                                    }
-                                }
-                                name @ "world_to_tex.wgsl" => {
-                                    if let Some(p) = load_pipeline(
-                                        "src/shaders/world_to_tex.wgsl",
-                                        &device,
-                                        &buf_to_tex_compute_pipeline_layout,
-                                    ) {
-                                        buf_to_tex_pipeline = p;
-                                        println!("Reloaded Shader: {}", name);
-                                    }
-                                }
-                                _ => (),
-                            }
-                        }
-
-                        _ => (),
+                let changed = changed_shaders(&file_rx);
+                for path in shader_loader.affected(&changed, &SHADERS) {
+                    if path == RENDER_SHADER {
+                        reload(&mut render_pipeline, path, &mut shader_errors, || {
+                            shader_loader.load_render_pipeline(
+                                path,
+                                &device,
+                                &pipeline_layout,
+                                format,
+                                layouts::RENDER,
+                            )
+                        });
+                    } else if path == DOWNSAMPLE_SHADER {
+                        mip_chain.reload_shader(&device, &shader_loader, &mut shader_errors);
+                    } else if !sim.reload_shader(path, &device, &shader_loader, &mut shader_errors)
+                    {
+                        unreachable!();
                     }
                 }
             }
@@ -728,63 +686,90 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                 ..
             } => *control_flow = ControlFlow::Exit,
             Event::WindowEvent {
-                event: win_event, ..
-            } => match win_event {
-                WindowEvent::KeyboardInput { input, .. } => match input {
-                    KeyboardInput {
-                        state: ElementState::Pressed,
-                        virtual_keycode: Some(VirtualKeyCode::S),
-                        ..
-                    } => {
-                        let start = SystemTime::now();
-                        let since_the_epoch = start.duration_since(UNIX_EPOCH).unwrap();
-                        let filepath = format!("images/image-{}.png", since_the_epoch.as_millis());
-                        save_image(
-                            &device,
-                            &world_texture,
-                            &world_texture_copy_buffer,
-                            &queue,
-                            &save_img_pool,
-                            filepath,
-                        );
-                    }
-                    KeyboardInput {
-                        state: ElementState::Pressed,
-                        virtual_keycode: Some(VirtualKeyCode::R),
-                        ..
-                    } => {
-                        recording = match recording {
-                            RecordingState::Off => start_recording(),
-                            RecordingState::On(_, _) => RecordingState::Off,
-                        };
-                    }
-                    KeyboardInput {
-                        state: ElementState::Pressed,
-                        virtual_keycode: Some(VirtualKeyCode::Space),
-                        ..
-                    } => {
-                        queue.write_buffer(&slimes_buffer, 0, bytemuck::cast_slice(&slimes));
-                        queue.write_buffer(
-                            &current_world_buffer,
-                            0,
-                            bytemuck::cast_slice(&init_world_data),
-                        );
-                        queue.write_buffer(
-                            &next_world_buffer,
-                            0,
-                            bytemuck::cast_slice(&init_world_data),
-                        );
-                        queue.submit(None);
-                    }
-                    KeyboardInput {
-                        state: ElementState::Pressed,
-                        virtual_keycode: Some(VirtualKeyCode::C),
-                        ..
-                    } => {
-                        moving_to_center = (moving_to_center + 1) % 2;
-                    }
-                    _ => (),
-                },
+                event: WindowEvent::KeyboardInput { input, .. },
+                ..
+            } => match input {
+                KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::S),
+                    ..
+                } => {
+                    let _ = control_tx.send(Action::Screenshot);
+                }
+                KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::G),
+                    ..
+                } => {
+                    save_network(&device, &queue, &sim, params.network, &save_img_pool);
+                }
+                KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::R),
+                    ..
+                } => {
+                    let _ = control_tx.send(Action::Record(None));
+                }
+                KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::Space),
+                    ..
+                } => {
+                    let _ = control_tx.send(Action::Reset);
+                }
+                KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::C),
+                    ..
+                } => {
+                    let _ = control_tx.send(Action::MoveToCenter(None));
+                }
+                KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::H),
+                    ..
+                } => {
+                    show_hud = !show_hud;
+                }
+                KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::F),
+                    ..
+                } => {
+                    filter = filter.next();
+                    println!("Filter: {}", filter);
+                    queue.write_buffer(
+                        &present_buffer,
+                        0,
+                        bytemuck::cast_slice(&[PresentConfig::new(filter)]),
+                    );
+                }
+                KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::P),
+                    ..
+                } => {
+                    param_panel.toggle();
+                }
+                KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode:
+                        Some(
+                            VirtualKeyCode::Equals
+                            | VirtualKeyCode::Plus
+                            | VirtualKeyCode::NumpadAdd,
+                        ),
+                    ..
+                } => {
+                    requested_slimes = Some(sim.size.num_slimes.saturating_add(SLIME_STEP));
+                }
+                KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract),
+                    ..
+                } => {
+                    requested_slimes = Some(sim.size.num_slimes.saturating_sub(SLIME_STEP));
+                }
                 _ => (),
             },
             _ => (),
@@ -802,65 +787,308 @@ fn start_recording() -> RecordingState {
     RecordingState::On(now, 0)
 }
 
+/// Saves `images/image-<time>.png`, scaled down to a width with a filter if `resize` is given.
+fn save_screenshot(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    sim: &Simulation,
+    save_img_pool: &Pool<ThunkWorker<()>>,
+    resize: Option<(Filter, u32)>,
+) {
+    let start = SystemTime::now();
+    let since_the_epoch = start.duration_since(UNIX_EPOCH).unwrap();
+    let filepath = format!("images/image-{}.png", since_the_epoch.as_millis());
+    let (filter, width) = match resize {
+        Some(resize) => resize,
+        None => return save_image(device, queue, sim, save_img_pool, filepath),
+    };
+    let data = sim.capture(device, queue);
+    let (world_width, world_height) = sim.size.world;
+    save_img_pool.execute(Thunk::of(move || {
+        let image = image::RgbaImage::from_raw(world_width, world_height, data).unwrap();
+        filter.resize(&image, width).save(&filepath).unwrap();
+    }));
+}
+
+/// Reads back the trail and extracts the network on the worker pool, writing
+/// `networks/network-<time>.graphml` and `.geojson`.
+fn save_network(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    sim: &Simulation,
+    params: NetworkParams,
+    save_img_pool: &Pool<ThunkWorker<()>>,
+) {
+    let since_the_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    let path = PathBuf::from(format!("networks/network-{}", since_the_epoch.as_millis()));
+    let world = network::read_world(device, queue, &sim.current_world_buffer, &sim.size);
+    let size = sim.size.world;
+    save_img_pool.execute(Thunk::of(move || {
+        match network::export(&world, size, &params, &path) {
+            Ok(graph) => println!(
+                "Network Saved: {} ({} nodes, {} edges)",
+                path.display(),
+                graph.nodes.len(),
+                graph.edges.len()
+            ),
+            Err(e) => eprintln!("Error saving network {}: {}", path.display(), e),
+        }
+    }));
+}
+
 fn save_image(
     device: &wgpu::Device,
-    world_texture: &wgpu::Texture,
-    world_texture_copy_buffer: &wgpu::Buffer,
     queue: &wgpu::Queue,
+    sim: &Simulation,
     save_img_pool: &Pool<ThunkWorker<()>>,
     filepath: String,
 ) {
     // Save Current World Texture;
-    let mut encoder = device.create_command_encoder(&Default::default());
-    encoder.copy_texture_to_buffer(
-        wgpu::ImageCopyTexture {
-            texture: &world_texture,
-            mip_level: 0,
-            origin: wgpu::Origin3d::ZERO,
-            aspect: wgpu::TextureAspect::All,
-        },
-        wgpu::ImageCopyBuffer {
-            buffer: &world_texture_copy_buffer,
-            layout: wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: NonZeroU32::new(4 * WORLD_SIZE.0),
-                rows_per_image: NonZeroU32::new(WORLD_SIZE.1),
+    let data = sim.capture(device, queue);
+    let (width, height) = sim.size.world;
+    save_img_pool.execute(Thunk::of(move || {
+        image::save_buffer_with_format(
+            &filepath,
+            &data,
+            width,
+            height,
+            image::ColorType::Rgba8,
+            image::ImageFormat::Png,
+        )
+        .unwrap();
+        // println!("Image Saved: {}", &filepath);
+    }));
+}
+
+/// What to run, the window unless a subcommand is given.
+enum Command {
+    Window,
+    /// Headless parameter search, with an optional config file.
+    Explore {
+        config: Option<PathBuf>,
+    },
+    /// Headless rendering of every frame.
+    Render {
+        frames: Option<u64>,
+        seed: Option<u64>,
+        preset: Option<String>,
+    },
+    /// Headless wallpapers, one image per seed.
+    Wallpaper(wallpaper::WallpaperOptions),
+    /// Sends one OSC message to a running window, to try `--osc` without a controller.
+    OscSend {
+        to: String,
+        /// The address followed by the argument values.
+        message: Vec<String>,
+    },
+}
+
+/// Command line options.
+struct Args {
+    command: Command,
+    /// Load shaders from this directory and hot reload them, instead of the embedded copies.
+    shader_dir: Option<PathBuf>,
+    /// CSV or GeoJSON file of food sources.
+    food: Option<PathBuf>,
+    /// TOML file of parameter keyframes to play back.
+    timeline: Option<PathBuf>,
+    /// WAV file driving parameters.
+    audio: Option<PathBuf>,
+    /// TOML file mapping audio bands to parameters, a default mapping if missing.
+    audio_map: Option<PathBuf>,
+    /// Port or address to listen for OSC messages on.
+    osc: Option<String>,
+    /// Port on localhost to serve the control API on.
+    http: Option<u16>,
+    filter: Option<Filter>,
+    screenshot_width: Option<u32>,
+}
+
+impl Args {
+    fn parse() -> Result<Args, pico_args::Error> {
+        let mut args = pico_args::Arguments::from_env();
+        let command = match args.subcommand()?.as_deref() {
+            None => Command::Window,
+            Some("explore") => Command::Explore {
+                config: args.opt_value_from_str("--config")?,
             },
-        },
-        wgpu::Extent3d {
-            width: WORLD_SIZE.0,
-            height: WORLD_SIZE.1,
-            depth_or_array_layers: 1,
-        },
-    );
-    queue.submit(Some(encoder.finish()));
-    // Download Buffer from GPU
-    {
-        let buffer_slice = world_texture_copy_buffer.slice(..);
-        let mapping = buffer_slice.map_async(wgpu::MapMode::Read);
-        device.poll(wgpu::Maintain::Wait);
-        pollster::block_on(mapping).unwrap();
-        let mut data = vec![0u8; (WORLD_SIZE.0 * WORLD_SIZE.1 * 4) as usize];
-        data.copy_from_slice(&buffer_slice.get_mapped_range());
-
-        let fp = filepath.clone();
-        save_img_pool.execute(Thunk::of(move || {
-            image::save_buffer_with_format(
-                &fp,
-                &data,
-                WORLD_SIZE.0,
-                WORLD_SIZE.1,
-                image::ColorType::Rgba8,
-                image::ImageFormat::Png,
-            )
-            .unwrap();
-            // println!("Image Saved: {}", &fp);
-        }));
+            Some("render") => Command::Render {
+                frames: args.opt_value_from_str("--frames")?,
+                seed: args.opt_value_from_str("--seed")?,
+                preset: args.opt_value_from_str("--preset")?,
+            },
+            Some("wallpaper") => Command::Wallpaper(wallpaper::WallpaperOptions {
+                device: args
+                    .opt_value_from_str("--device")?
+                    .unwrap_or_else(|| "monitor".to_owned()),
+                slimes: args.opt_value_from_str("--slimes")?,
+                preset: args.opt_value_from_str("--preset")?,
+                seed: args.opt_value_from_str("--seed")?,
+                frames: args.opt_value_from_str("--frames")?.unwrap_or(1200),
+                count: args.opt_value_from_str("--count")?.unwrap_or(1),
+                vary: args.opt_value_from_str("--vary")?.unwrap_or(0.0),
+                tile: args.opt_value_from_str("--tile")?,
+                halo: args.opt_value_from_str("--halo")?.unwrap_or(96),
+            }),
+            Some("osc-send") => Command::OscSend {
+                to: args
+                    .opt_value_from_str("--to")?
+                    .unwrap_or_else(|| "9000".to_owned()),
+                message: Vec::new(),
+            },
+            Some(other) => {
+                return Err(pico_args::Error::ArgumentParsingFailed {
+                    cause: format!("unknown command: {}", other),
+                })
+            }
+        };
+        let mut parsed = Args {
+            command,
+            shader_dir: args.opt_value_from_str("--shader-dir")?,
+            food: args.opt_value_from_str("--food")?,
+            timeline: args.opt_value_from_str("--timeline")?,
+            audio: args.opt_value_from_str("--audio")?,
+            audio_map: args.opt_value_from_str("--audio-map")?,
+            osc: args.opt_value_from_str("--osc")?,
+            http: args.opt_value_from_str("--http")?,
+            filter: args.opt_value_from_str("--filter")?,
+            screenshot_width: args.opt_value_from_str("--screenshot-width")?,
+        };
+        let rest = args.finish();
+        if let Command::OscSend { message, .. } = &mut parsed.command {
+            // Everything left is the message, negative values included
+            *message = rest
+                .into_iter()
+                .map(|arg| arg.into_string())
+                .collect::<Result<_, _>>()
+                .map_err(|_| pico_args::Error::NonUtf8Argument)?;
+            if message.is_empty() {
+                return Err(pico_args::Error::ArgumentParsingFailed {
+                    cause: "osc-send needs an address such as /species/0/speed".to_owned(),
+                });
+            }
+        } else if !rest.is_empty() {
+            return Err(pico_args::Error::ArgumentParsingFailed {
+                cause: format!("unexpected arguments: {:?}", rest),
+            });
+        }
+        Ok(parsed)
     }
-    world_texture_copy_buffer.unmap();
+}
+
+/// Unwraps `result`, or exits with the error prefixed by `context`.
+fn or_exit<T, E: std::fmt::Display>(result: Result<T, E>, context: &str) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("{}: {}", context, e);
+        std::process::exit(1);
+    })
 }
 
 fn main() {
+    let args = Args::parse().unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        eprintln!("usage: gpu_slime_mould [--shader-dir <dir>] [--food <file.csv|file.geojson>]");
+        eprintln!("           [--timeline <file.toml>] [--audio <file.wav> [--audio-map <file.toml>]]");
+        eprintln!("           [--osc <port|address>] [--http <port>]");
+        eprintln!("           [--filter <nearest|linear|area|bicubic>] [--screenshot-width <px>]");
+        eprintln!("       gpu_slime_mould explore [--config <file.toml>] [--shader-dir <dir>] [--food <file>]");
+        eprintln!("       gpu_slime_mould render [--frames <n>] [--seed <n>] [--preset <name>]");
+        eprintln!("           [--timeline <file.toml>] [--audio <file.wav> [--audio-map <file.toml>]] ...");
+        eprintln!("       gpu_slime_mould wallpaper [--device <name|WxH>] [--preset <name>] [--seed <n>]");
+        eprintln!("           [--frames <n>] [--slimes <n>] [--count <n> [--vary <fraction>]]");
+        eprintln!("           [--tile <px> [--halo <px>]] ...");
+        eprintln!("       gpu_slime_mould osc-send [--to <port|address>] <address> [values...]");
+        std::process::exit(2);
+    });
+    let shader_dir = match args.shader_dir {
+        Some(dir) => ShaderDir::Disk(dir),
+        None => ShaderDir::Embedded,
+    };
+    if let Command::Explore { config } = args.command {
+        let config = match config {
+            Some(path) => or_exit(
+                ExploreConfig::load(&path),
+                &format!("error loading {}", path.display()),
+            ),
+            None => ExploreConfig::default(),
+        };
+        or_exit(
+            explore::run(&config, shader_dir, args.food.as_deref()),
+            "error",
+        );
+        return;
+    }
+    if let Command::OscSend { to, message } = &args.command {
+        let to = or_exit(osc::listen_address(to), "error in --to");
+        let message = osc::message_from_args(&message[0], &message[1..]);
+        or_exit(osc::send(to, &message), "error sending OSC");
+        return;
+    }
+    if let Command::Wallpaper(options) = &args.command {
+        or_exit(
+            wallpaper::run(options, shader_dir, args.food.as_deref()),
+            "error",
+        );
+        return;
+    }
+
+    let timeline = args.timeline.map(|path| {
+        or_exit(
+            Timeline::load(&path),
+            &format!("error loading timeline {}", path.display()),
+        )
+    });
+    let audio = args.audio.as_ref().map(|path| {
+        let config = match &args.audio_map {
+            Some(map) => or_exit(
+                AudioConfig::load(map),
+                &format!("error loading {}", map.display()),
+            ),
+            None => AudioConfig::default(),
+        };
+        or_exit(
+            AudioReactive::load(path, &config),
+            &format!("error loading audio {}", path.display()),
+        )
+    });
+    let animation = Animation { timeline, audio };
+
+    if let Command::Render {
+        frames,
+        seed,
+        preset,
+    } = args.command
+    {
+        let size = SimSize {
+            world: WORLD_SIZE,
+            num_slimes: NUM_SLIMES,
+        };
+        let options = render::RenderOptions {
+            frames,
+            seed,
+            preset,
+            audio_path: args.audio,
+        };
+        or_exit(
+            render::run(size, shader_dir, args.food.as_deref(), &animation, &options),
+            "error",
+        );
+        return;
+    }
+
+    // The window starts from the default parameters
+    let food_sources = match args.food {
+        Some(path) => or_exit(
+            food::load(&path, WORLD_SIZE, FoodParams::default().radius),
+            "error loading food",
+        ),
+        None => Vec::new(),
+    };
+
+    let osc_address = args
+        .osc
+        .map(|arg| or_exit(osc::listen_address(&arg), "error in --osc"));
+
     let event_loop = EventLoop::new();
     // let mut monitor = event_loop.available_monitors();
     let window = winit::window::WindowBuilder::new()
@@ -873,34 +1101,17 @@ fn main() {
         .with_inner_size(winit::dpi::LogicalSize::new(WINDOW_SIZE.0, WINDOW_SIZE.1))
         .build(&event_loop)
         .unwrap();
-    pollster::block_on(run(event_loop, window));
-}
-
-fn load_pipeline(
-    path: &str,
-    device: &wgpu::Device,
-    pipeline_layout: &wgpu::PipelineLayout,
-) -> Option<ComputePipeline> {
-    let shader_code = std::fs::read_to_string(path).unwrap();
-    match naga::front::wgsl::parse_str(&shader_code) {
-        Ok(_) => {
-            let cs_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
-                label: None,
-                source: wgpu::ShaderSource::Wgsl(shader_code.into()),
-            });
-
-            Some(
-                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-                    label: None,
-                    layout: Some(&pipeline_layout),
-                    module: &cs_module,
-                    entry_point: "main",
-                }),
-            )
-        }
-        Err(ref e) => {
-            e.emit_to_stderr(&shader_code);
-            None
-        }
-    }
+    pollster::block_on(run(
+        event_loop,
+        window,
+        shader_dir,
+        food_sources,
+        animation,
+        WindowOptions {
+            osc_address,
+            http_port: args.http,
+            filter: args.filter.unwrap_or(Filter::Area),
+            screenshot_width: args.screenshot_width,
+        },
+    ));
 }
@@ -0,0 +1,592 @@
+//! Extracts the transport network the slimes have formed from the trail map. The trail is
+//! thresholded, thinned to a one pixel wide skeleton, and the skeleton is split into nodes
+//! (junctions and dead ends) joined by edges, which are exported as GraphML and GeoJSON.
+
+use std::fmt::Write as _;
+use std::io;
+use std::path::Path;
+
+use wgpu::BufferUsages;
+
+use crate::params::{NetworkParams, NUM_SPECIES};
+use crate::sizing::SimSize;
+
+/// The 8 neighbours of a pixel, clockwise from north.
+const NEIGHBOURS: [(i32, i32); 8] = [
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+];
+const NO_NODE: u32 = u32::MAX;
+
+pub struct Node {
+    /// Centre of the junction in world coordinates.
+    pub pos: [f32; 2],
+    pub degree: u32,
+}
+
+pub struct Edge {
+    pub source: usize,
+    pub target: usize,
+    /// Length of the path along the skeleton, in pixels.
+    pub length: f32,
+    /// Mean trail strength along the edge, how well used the connection is.
+    pub weight: f32,
+    /// The skeleton pixels from `source` to `target`.
+    pub path: Vec<[f32; 2]>,
+}
+
+impl Edge {
+    fn reverse(&mut self) {
+        std::mem::swap(&mut self.source, &mut self.target);
+        self.path.reverse();
+    }
+}
+
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+/// Reads the trail map back from the GPU. Blocks until the GPU has caught up.
+pub fn read_world(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+    size: &SimSize,
+) -> Vec<f32> {
+    let staging = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: size.world_buffer_size(),
+        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&Default::default());
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, size.world_buffer_size());
+    queue.submit(Some(encoder.finish()));
+
+    let slice = staging.slice(..);
+    let mapping = slice.map_async(wgpu::MapMode::Read);
+    device.poll(wgpu::Maintain::Wait);
+    pollster::block_on(mapping).unwrap();
+    let world = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+    staging.unmap();
+    world
+}
+
+/// A binary image the size of the world, with helpers for walking neighbours.
+struct Mask {
+    width: usize,
+    height: usize,
+    pixels: Vec<bool>,
+}
+
+impl Mask {
+    fn neighbour(&self, i: usize, (dx, dy): (i32, i32)) -> usize {
+        (i as isize + dx as isize + dy as isize * self.width as isize) as usize
+    }
+
+    /// Which of the 8 neighbours are set, clockwise from north. Only valid away from the
+    /// border, which `threshold` keeps clear.
+    fn neighbours(&self, i: usize) -> [bool; 8] {
+        NEIGHBOURS.map(|offset| self.pixels[self.neighbour(i, offset)])
+    }
+}
+
+/// Number of set neighbours, and number of separate runs of them around the pixel. A pixel
+/// with three or more runs is where branches meet.
+fn count_neighbours(n: &[bool; 8]) -> (usize, usize) {
+    let count = n.iter().filter(|&&b| b).count();
+    let runs = (0..8).filter(|&k| !n[k] && n[(k + 1) % 8]).count();
+    (count, runs)
+}
+
+/// Trail of all species summed per pixel. The world is a plane per species.
+pub fn total_trail(world: &[f32], plane: usize) -> Vec<f32> {
+    let mut total = vec![0.0; plane];
+    for species in world.chunks_exact(plane).take(NUM_SPECIES) {
+        for (t, v) in total.iter_mut().zip(species) {
+            *t += v;
+        }
+    }
+    total
+}
+
+/// Pixels whose summed trail is at least `threshold`. The outermost pixels are always cleared
+/// so every skeleton pixel has 8 neighbours.
+fn threshold(trail: &[f32], width: usize, height: usize, threshold: f32) -> Mask {
+    let mut pixels: Vec<bool> = trail.iter().map(|&t| t >= threshold).collect();
+    for x in 0..width {
+        pixels[x] = false;
+        pixels[(height - 1) * width + x] = false;
+    }
+    for y in 0..height {
+        pixels[y * width] = false;
+        pixels[y * width + width - 1] = false;
+    }
+    Mask {
+        width,
+        height,
+        pixels,
+    }
+}
+
+/// Zhang-Suen thinning, peels pixels off the outside of every strand until it is one pixel
+/// wide without breaking it apart.
+fn skeletonize(mask: &mut Mask) {
+    let mut removed = Vec::new();
+    loop {
+        let mut changed = false;
+        for pass in 0..2 {
+            for y in 1..mask.height - 1 {
+                for x in 1..mask.width - 1 {
+                    let i = y * mask.width + x;
+                    if !mask.pixels[i] {
+                        continue;
+                    }
+                    let n = mask.neighbours(i);
+                    let (count, runs) = count_neighbours(&n);
+                    if !(2..=6).contains(&count) || runs != 1 {
+                        continue;
+                    }
+                    let (north, east, south, west) = (n[0], n[2], n[4], n[6]);
+                    let keep = if pass == 0 {
+                        east && south && (north || west)
+                    } else {
+                        north && west && (east || south)
+                    };
+                    if !keep {
+                        removed.push(i);
+                    }
+                }
+            }
+            changed |= !removed.is_empty();
+            for i in removed.drain(..) {
+                mask.pixels[i] = false;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+}
+
+/// Turns a skeleton into a graph. Dead ends and junctions become nodes, with adjacent junction
+/// pixels merged into one node, and the strands between them become edges. A strand closed on
+/// itself without any junction gets a node on one of its pixels and becomes a loop edge.
+fn build_graph(skeleton: &Mask, trail: &[f32]) -> Graph {
+    let width = skeleton.width;
+    let pos = |i: usize| [(i % width) as f32, (i / width) as f32];
+
+    let is_node = |i: usize| {
+        let (count, runs) = count_neighbours(&skeleton.neighbours(i));
+        count == 1 || runs >= 3
+    };
+    let mut node_of = vec![NO_NODE; skeleton.pixels.len()];
+    let mut node_pixels: Vec<Vec<usize>> = Vec::new();
+    for start in 0..skeleton.pixels.len() {
+        if !skeleton.pixels[start] || node_of[start] != NO_NODE || !is_node(start) {
+            continue;
+        }
+        let id = node_pixels.len() as u32;
+        let mut pixels = vec![start];
+        node_of[start] = id;
+        let mut k = 0;
+        while k < pixels.len() {
+            for offset in NEIGHBOURS {
+                let j = skeleton.neighbour(pixels[k], offset);
+                if skeleton.pixels[j] && node_of[j] == NO_NODE && is_node(j) {
+                    node_of[j] = id;
+                    pixels.push(j);
+                }
+            }
+            k += 1;
+        }
+        node_pixels.push(pixels);
+    }
+
+    let mut visited = vec![false; skeleton.pixels.len()];
+    let mut edges = Vec::new();
+    let mut source = 0;
+    let mut next_unvisited = 0;
+    loop {
+        if source == node_pixels.len() {
+            // Every strand reachable from a node is walked, what is left are closed loops
+            let start = (next_unvisited..skeleton.pixels.len())
+                .find(|&i| skeleton.pixels[i] && node_of[i] == NO_NODE && !visited[i]);
+            match start {
+                Some(start) => {
+                    node_of[start] = source as u32;
+                    node_pixels.push(vec![start]);
+                    next_unvisited = start + 1;
+                }
+                None => break,
+            }
+        }
+        for &from in &node_pixels[source] {
+            for offset in NEIGHBOURS {
+                let first = skeleton.neighbour(from, offset);
+                if !skeleton.pixels[first] || node_of[first] != NO_NODE || visited[first] {
+                    continue;
+                }
+                // Walk along the strand until it reaches a node
+                let mut path = vec![pos(from)];
+                let mut total_strength = 0.0;
+                let mut current = first;
+                let end = loop {
+                    visited[current] = true;
+                    path.push(pos(current));
+                    total_strength += trail[current];
+                    let end = NEIGHBOURS
+                        .iter()
+                        .map(|&o| skeleton.neighbour(current, o))
+                        .find(|&j| {
+                            let node = node_of[j];
+                            node != NO_NODE && (node as usize != source || path.len() > 3)
+                        });
+                    if end.is_some() {
+                        break end;
+                    }
+                    // Orthogonal steps first, so corners of a staircase aren't skipped
+                    let next = [0, 2, 4, 6, 1, 3, 5, 7]
+                        .iter()
+                        .map(|&k| skeleton.neighbour(current, NEIGHBOURS[k]))
+                        .find(|&j| skeleton.pixels[j] && node_of[j] == NO_NODE && !visited[j]);
+                    match next {
+                        Some(next) => current = next,
+                        None => break None,
+                    }
+                };
+                if let Some(end) = end {
+                    let interior = path.len() - 1;
+                    path.push(pos(end));
+                    let length = path
+                        .windows(2)
+                        .map(|w| (w[1][0] - w[0][0]).hypot(w[1][1] - w[0][1]))
+                        .sum();
+                    edges.push(Edge {
+                        source,
+                        target: node_of[end] as usize,
+                        length,
+                        weight: total_strength / interior as f32,
+                        path,
+                    });
+                }
+            }
+        }
+        source += 1;
+    }
+
+    let nodes = node_pixels
+        .iter()
+        .map(|pixels| {
+            let sum = pixels.iter().fold([0.0, 0.0], |acc, &i| {
+                let p = pos(i);
+                [acc[0] + p[0], acc[1] + p[1]]
+            });
+            let n = pixels.len() as f32;
+            Node {
+                pos: [sum[0] / n, sum[1] / n],
+                degree: 0,
+            }
+        })
+        .collect();
+    let mut graph = Graph { nodes, edges };
+    graph.count_degrees();
+    graph
+}
+
+impl Graph {
+    fn count_degrees(&mut self) {
+        for node in &mut self.nodes {
+            node.degree = 0;
+        }
+        for edge in &self.edges {
+            self.nodes[edge.source].degree += 1;
+            self.nodes[edge.target].degree += 1;
+        }
+    }
+
+    /// Removes dead end edges and loops shorter than `min_length`, the short spurs thinning
+    /// leaves on the sides of thick strands. Junctions left with two edges are joined into one
+    /// edge, and the nodes left without edges are dropped.
+    fn prune(&mut self, min_length: f32) {
+        let nodes = &self.nodes;
+        self.edges.retain(|e| {
+            let dead_end = nodes[e.source].degree == 1 || nodes[e.target].degree == 1;
+            let spur = dead_end || e.source == e.target;
+            !spur || e.length >= min_length
+        });
+        self.count_degrees();
+        self.join_through_nodes();
+        self.count_degrees();
+
+        let mut new_index = vec![0; self.nodes.len()];
+        let mut kept = 0;
+        for (i, node) in self.nodes.iter().enumerate() {
+            new_index[i] = kept;
+            if node.degree > 0 {
+                kept += 1;
+            }
+        }
+        self.nodes.retain(|n| n.degree > 0);
+        for edge in &mut self.edges {
+            edge.source = new_index[edge.source];
+            edge.target = new_index[edge.target];
+        }
+    }
+
+    /// Joins each pair of edges meeting at a node of degree 2 into one edge through it. The
+    /// weight of the joined edge is the length weighted mean of the two.
+    fn join_through_nodes(&mut self) {
+        let mut incident = vec![Vec::new(); self.nodes.len()];
+        for (i, edge) in self.edges.iter().enumerate() {
+            incident[edge.source].push(i);
+            incident[edge.target].push(i);
+        }
+        let mut edges: Vec<Option<Edge>> = self.edges.drain(..).map(Some).collect();
+        for node in 0..self.nodes.len() {
+            let (a, b) = match incident[node][..] {
+                [a, b] if a != b => (a, b),
+                // A loop through the node has nothing to be joined with
+                _ => continue,
+            };
+            let mut first = edges[a].take().unwrap();
+            let mut second = edges[b].take().unwrap();
+            if first.target != node {
+                first.reverse();
+            }
+            if second.source != node {
+                second.reverse();
+            }
+            let length = first.length + second.length;
+            let weight = if length > 0.0 {
+                (first.weight * first.length + second.weight * second.length) / length
+            } else {
+                first.weight
+            };
+            // Both paths end on a pixel of the node, which may be the same one
+            let shared = first.path.last() == second.path.first();
+            first
+                .path
+                .extend(second.path.drain(..).skip(shared as usize));
+            incident[node].clear();
+            for i in &mut incident[second.target] {
+                if *i == b {
+                    *i = a;
+                }
+            }
+            edges[a] = Some(Edge {
+                source: first.source,
+                target: second.target,
+                length,
+                weight,
+                path: first.path,
+            });
+        }
+        self.edges = edges.into_iter().flatten().collect();
+    }
+
+    /// Undirected GraphML with node positions and edge lengths and weights as attributes.
+    pub fn to_graphml(&self) -> String {
+        let mut out = String::new();
+        out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        out.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+        for (id, target) in [
+            ("x", "node"),
+            ("y", "node"),
+            ("length", "edge"),
+            ("weight", "edge"),
+        ] {
+            writeln!(
+                out,
+                "  <key id=\"{0}\" for=\"{1}\" attr.name=\"{0}\" attr.type=\"double\"/>",
+                id, target
+            )
+            .unwrap();
+        }
+        out.push_str("  <graph id=\"network\" edgedefault=\"undirected\">\n");
+        for (i, node) in self.nodes.iter().enumerate() {
+            writeln!(
+                out,
+                "    <node id=\"n{}\"><data key=\"x\">{}</data><data key=\"y\">{}</data></node>",
+                i, node.pos[0], node.pos[1]
+            )
+            .unwrap();
+        }
+        for edge in &self.edges {
+            writeln!(
+                out,
+                "    <edge source=\"n{}\" target=\"n{}\"><data key=\"length\">{}</data>\
+                 <data key=\"weight\">{}</data></edge>",
+                edge.source, edge.target, edge.length, edge.weight
+            )
+            .unwrap();
+        }
+        out.push_str("  </graph>\n</graphml>\n");
+        out
+    }
+
+    /// A FeatureCollection of node points and edge line strings. GeoJSON's y axis points up,
+    /// so y is flipped within a world of `height` to keep the network the right way up.
+    pub fn to_geojson(&self, height: u32) -> String {
+        let point = |p: [f32; 2]| vec![p[0] as f64, (height as f32 - p[1]) as f64];
+        let feature = |value, properties: Vec<(&str, geojson::JsonValue)>| geojson::Feature {
+            geometry: Some(geojson::Geometry::new(value)),
+            properties: Some(
+                properties
+                    .into_iter()
+                    .map(|(k, v)| (k.to_owned(), v))
+                    .collect(),
+            ),
+            ..Default::default()
+        };
+        let nodes = self.nodes.iter().enumerate().map(|(i, node)| {
+            feature(
+                geojson::Value::Point(point(node.pos)),
+                vec![("id", i.into()), ("degree", node.degree.into())],
+            )
+        });
+        let edges = self.edges.iter().map(|edge| {
+            feature(
+                geojson::Value::LineString(edge.path.iter().map(|&p| point(p)).collect()),
+                vec![
+                    ("source", edge.source.into()),
+                    ("target", edge.target.into()),
+                    ("length", edge.length.into()),
+                    ("weight", edge.weight.into()),
+                ],
+            )
+        });
+        geojson::FeatureCollection {
+            bbox: None,
+            features: nodes.chain(edges).collect(),
+            foreign_members: None,
+        }
+        .to_string()
+    }
+}
+
+/// Extracts the network from a world of `size`, as read back by `read_world`.
+pub fn extract(world: &[f32], size: (u32, u32), params: &NetworkParams) -> Graph {
+    let (width, height) = (size.0 as usize, size.1 as usize);
+    let trail = total_trail(world, width * height);
+    let mut mask = threshold(&trail, width, height, params.threshold);
+    skeletonize(&mut mask);
+    let mut graph = build_graph(&mask, &trail);
+    graph.prune(params.min_spur_length);
+    graph
+}
+
+/// Extracts the network and writes it to `<path>.graphml` and `<path>.geojson`.
+pub fn export(
+    world: &[f32],
+    size: (u32, u32),
+    params: &NetworkParams,
+    path: &Path,
+) -> io::Result<Graph> {
+    let graph = extract(world, size, params);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path.with_extension("graphml"), graph.to_graphml())?;
+    std::fs::write(path.with_extension("geojson"), graph.to_geojson(size.1))?;
+    Ok(graph)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: (u32, u32) = (32, 32);
+
+    /// A world with trail on the first species' plane wherever `on` is true.
+    fn world(on: impl Fn(usize, usize) -> bool) -> Vec<f32> {
+        let (width, height) = (SIZE.0 as usize, SIZE.1 as usize);
+        let mut world = vec![0.0; width * height * NUM_SPECIES];
+        for y in 0..height {
+            for x in 0..width {
+                if on(x, y) {
+                    world[y * width + x] = 1.0;
+                }
+            }
+        }
+        world
+    }
+
+    fn extract_with_spurs(world: &[f32], min_spur_length: f32) -> Graph {
+        let params = NetworkParams {
+            threshold: 0.5,
+            min_spur_length,
+        };
+        extract(world, SIZE, &params)
+    }
+
+    fn count_degree(graph: &Graph, degree: u32) -> usize {
+        graph.nodes.iter().filter(|n| n.degree == degree).count()
+    }
+
+    #[test]
+    fn plus_has_one_junction_and_four_ends() {
+        // Three pixel wide arms, so thinning has something to peel off
+        let world = world(|x, y| {
+            let arm = |a: usize, b: usize| (15..=17).contains(&a) && (4..=28).contains(&b);
+            arm(x, y) || arm(y, x)
+        });
+        let graph = extract_with_spurs(&world, 0.0);
+        assert_eq!(graph.nodes.len(), 5);
+        assert_eq!(count_degree(&graph, 4), 1);
+        assert_eq!(count_degree(&graph, 1), 4);
+        assert_eq!(graph.edges.len(), 4);
+        for edge in &graph.edges {
+            assert!(edge.length > 8.0, "arm too short: {}", edge.length);
+            assert_eq!(edge.weight, 1.0);
+        }
+    }
+
+    #[test]
+    fn short_spurs_are_pruned() {
+        // A line with a three pixel spur sticking up from its middle
+        let world =
+            world(|x, y| (y == 16 && (4..=28).contains(&x)) || (x == 16 && (13..16).contains(&y)));
+
+        let unpruned = extract_with_spurs(&world, 0.0);
+        assert_eq!(unpruned.edges.len(), 3);
+        assert_eq!(count_degree(&unpruned, 1), 3);
+
+        // The junction is left with two edges, which are joined into one
+        let pruned = extract_with_spurs(&world, 5.0);
+        assert_eq!(pruned.edges.len(), 1);
+        assert_eq!(pruned.nodes.len(), 2);
+        assert_eq!(count_degree(&pruned, 1), 2);
+        let edge = &pruned.edges[0];
+        assert_ne!(edge.source, edge.target);
+        assert_eq!(edge.length, 24.0);
+        assert_eq!(edge.path.len(), 25);
+        assert_eq!(edge.weight, 1.0);
+    }
+
+    #[test]
+    fn closed_strand_becomes_a_loop() {
+        // A three pixel wide square ring without any junction
+        let world = world(|x, y| {
+            let ring = (x as i32 - 16).abs().max((y as i32 - 16).abs());
+            (7..=9).contains(&ring)
+        });
+        let graph = extract_with_spurs(&world, 5.0);
+        assert_eq!(graph.nodes.len(), 1);
+        assert_eq!(graph.nodes[0].degree, 2);
+        assert_eq!(graph.edges.len(), 1);
+        assert_eq!(graph.edges[0].source, graph.edges[0].target);
+        assert!(
+            graph.edges[0].length > 40.0,
+            "loop too short: {}",
+            graph.edges[0].length
+        );
+    }
+}
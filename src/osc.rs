@@ -0,0 +1,452 @@
+//! Open Sound Control over UDP. Messages are decoded here by hand, only the parts of OSC 1.0 a
+//! controller app sends: messages and bundles with int, float, string, blob, bool and 64 bit
+//! arguments.
+//!
+//! Addresses map onto actions: `/reset`, `/screenshot`, `/record` and `/center` act like
+//! `Space`, `S`, `R` and `C`, and any other address sets the parameter with that path, so
+//! `/species/0/speed 3.5` sets `species.0.speed`. `encode` and `send` are the other side, for
+//! the `osc-send` test client.
+
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::mpsc::Sender;
+use std::thread;
+
+use crate::control::Action;
+
+/// Largest datagram read, more than any controller sends.
+const MAX_PACKET: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arg {
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Blob(Vec<u8>),
+    Bool(bool),
+    /// `N` (nil) and `I` (impulse), which carry no value.
+    Empty,
+}
+
+impl Arg {
+    fn as_f32(&self) -> Option<f32> {
+        match self {
+            Arg::Int(v) => Some(*v as f32),
+            Arg::Float(v) => Some(*v as f32),
+            Arg::Bool(v) => Some(if *v { 1.0 } else { 0.0 }),
+            Arg::Str(s) => s.parse().ok(),
+            Arg::Blob(_) | Arg::Empty => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub address: String,
+    pub args: Vec<Arg>,
+}
+
+#[derive(Debug)]
+pub struct OscError(String);
+
+impl fmt::Display for OscError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid OSC packet: {}", self.0)
+    }
+}
+
+impl std::error::Error for OscError {}
+
+/// Reads the big endian values of a packet, every item padded to 4 bytes.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], OscError> {
+        if n > self.data.len() {
+            return Err(OscError("truncated".into()));
+        }
+        let (taken, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], OscError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    /// A null terminated string padded to a multiple of 4 bytes.
+    fn string(&mut self) -> Result<String, OscError> {
+        let end = self
+            .data
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| OscError("unterminated string".into()))?;
+        let text = std::str::from_utf8(&self.data[..end])
+            .map_err(|_| OscError("string is not UTF-8".into()))?
+            .to_owned();
+        self.take((end + 4) & !3)?;
+        Ok(text)
+    }
+
+    fn blob(&mut self) -> Result<Vec<u8>, OscError> {
+        let size = i32::from_be_bytes(self.array()?);
+        let size = usize::try_from(size).map_err(|_| OscError("negative blob size".into()))?;
+        let blob = self.take(size)?.to_vec();
+        self.take((4 - size % 4) % 4)?;
+        Ok(blob)
+    }
+}
+
+/// Decodes a packet into its messages, flattening bundles. Bundle time tags are ignored and
+/// everything applies immediately.
+pub fn parse(packet: &[u8]) -> Result<Vec<Message>, OscError> {
+    let mut messages = Vec::new();
+    parse_into(packet, &mut messages)?;
+    Ok(messages)
+}
+
+fn parse_into(packet: &[u8], messages: &mut Vec<Message>) -> Result<(), OscError> {
+    let mut reader = Reader { data: packet };
+    if packet.starts_with(b"#bundle\0") {
+        reader.take(16)?;
+        while !reader.data.is_empty() {
+            let size = i32::from_be_bytes(reader.array()?);
+            let size =
+                usize::try_from(size).map_err(|_| OscError("negative element size".into()))?;
+            parse_into(reader.take(size)?, messages)?;
+        }
+        return Ok(());
+    }
+
+    let address = reader.string()?;
+    if !address.starts_with('/') {
+        return Err(OscError(format!(
+            "address {:?} doesn't start with /",
+            address
+        )));
+    }
+    // Type tags are optional in old senders, no tags means no arguments
+    let tags = if reader.data.is_empty() {
+        String::from(",")
+    } else {
+        reader.string()?
+    };
+    let tags = tags
+        .strip_prefix(',')
+        .ok_or_else(|| OscError("type tags don't start with ,".into()))?;
+    let mut args = Vec::with_capacity(tags.len());
+    for tag in tags.chars() {
+        args.push(match tag {
+            'i' => Arg::Int(i32::from_be_bytes(reader.array()?) as i64),
+            'h' => Arg::Int(i64::from_be_bytes(reader.array()?)),
+            'f' => Arg::Float(f32::from_be_bytes(reader.array()?) as f64),
+            'd' => Arg::Float(f64::from_be_bytes(reader.array()?)),
+            's' | 'S' => Arg::Str(reader.string()?),
+            'b' => Arg::Blob(reader.blob()?),
+            'T' => Arg::Bool(true),
+            'F' => Arg::Bool(false),
+            'N' | 'I' => Arg::Empty,
+            // Time tags, colours and MIDI messages are all 4 or 8 bytes we have no use for
+            't' => {
+                reader.take(8)?;
+                Arg::Empty
+            }
+            'r' | 'm' | 'c' => {
+                reader.take(4)?;
+                Arg::Empty
+            }
+            other => return Err(OscError(format!("unsupported type tag {:?}", other))),
+        });
+    }
+    messages.push(Message { address, args });
+    Ok(())
+}
+
+/// The action a message asks for, `None` for messages that don't do anything. Triggers sent with
+/// a value of 0, which buttons send when they are released, are ignored.
+pub fn to_action(message: &Message) -> Option<Action> {
+    let value = message.args.first().and_then(Arg::as_f32);
+    let pressed = value.is_none_or(|v| v != 0.0);
+    let switch = value.map(|v| v != 0.0);
+    match message.address.as_str() {
+        "/reset" => pressed.then_some(Action::Reset),
+        "/screenshot" => pressed.then_some(Action::Screenshot),
+        "/record" => Some(Action::Record(switch)),
+        "/center" => Some(Action::MoveToCenter(switch)),
+        address => {
+            let path = address.trim_matches('/').replace('/', ".");
+            Some(Action::SetParam(path, value?))
+        }
+    }
+}
+
+/// Encodes a single message, the inverse of `parse`. Ints that fit in 32 bits are sent as `i`,
+/// floats as `f`, and empty arguments as nil.
+pub fn encode(message: &Message) -> Vec<u8> {
+    fn pad(packet: &mut Vec<u8>) {
+        packet.resize((packet.len() + 3) & !3, 0);
+    }
+    fn string(packet: &mut Vec<u8>, text: &str) {
+        packet.extend_from_slice(text.as_bytes());
+        packet.push(0);
+        pad(packet);
+    }
+
+    let mut tags = String::from(",");
+    let mut data = Vec::new();
+    for arg in &message.args {
+        match arg {
+            Arg::Int(v) => match i32::try_from(*v) {
+                Ok(v) => {
+                    tags.push('i');
+                    data.extend_from_slice(&v.to_be_bytes());
+                }
+                Err(_) => {
+                    tags.push('h');
+                    data.extend_from_slice(&v.to_be_bytes());
+                }
+            },
+            Arg::Float(v) => {
+                tags.push('f');
+                data.extend_from_slice(&(*v as f32).to_be_bytes());
+            }
+            Arg::Str(text) => {
+                tags.push('s');
+                string(&mut data, text);
+            }
+            Arg::Blob(blob) => {
+                tags.push('b');
+                data.extend_from_slice(&(blob.len() as i32).to_be_bytes());
+                data.extend_from_slice(blob);
+                pad(&mut data);
+            }
+            Arg::Bool(v) => tags.push(if *v { 'T' } else { 'F' }),
+            Arg::Empty => tags.push('N'),
+        }
+    }
+    let mut packet = Vec::new();
+    string(&mut packet, &message.address);
+    string(&mut packet, &tags);
+    packet.extend_from_slice(&data);
+    packet
+}
+
+/// A message from the command line of `osc-send`: values that parse as numbers are sent as
+/// floats, anything else as a string.
+pub fn message_from_args(address: &str, values: &[String]) -> Message {
+    Message {
+        address: address.to_owned(),
+        args: values
+            .iter()
+            .map(|v| match v.parse::<f64>() {
+                Ok(v) => Arg::Float(v),
+                Err(_) => Arg::Str(v.clone()),
+            })
+            .collect(),
+    }
+}
+
+/// Sends `message` to `address` from a port of its own.
+pub fn send(address: SocketAddr, message: &Message) -> io::Result<()> {
+    let local: SocketAddr = if address.is_ipv4() {
+        ([0, 0, 0, 0], 0).into()
+    } else {
+        ([0u16; 8], 0).into()
+    };
+    UdpSocket::bind(local)?.send_to(&encode(message), address)?;
+    Ok(())
+}
+
+/// Parses `--osc` and the `--to` of `osc-send`, either a port on localhost or a full address.
+pub fn listen_address(arg: &str) -> Result<SocketAddr, String> {
+    match arg.parse::<u16>() {
+        Ok(port) => Ok(SocketAddr::from(([127, 0, 0, 1], port))),
+        Err(_) => arg.parse().map_err(|_| {
+            format!(
+                "expected a port or an address such as 0.0.0.0:9000, got {}",
+                arg
+            )
+        }),
+    }
+}
+
+/// Listens for OSC packets on `address` in a thread of its own, queueing their actions on `tx`.
+pub fn listen(address: SocketAddr, tx: Sender<Action>) -> io::Result<()> {
+    let socket = UdpSocket::bind(address)?;
+    println!("Listening for OSC on udp://{}", socket.local_addr()?);
+    thread::spawn(move || {
+        let mut buffer = vec![0; MAX_PACKET];
+        loop {
+            let (size, sender) = match socket.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(e) => {
+                    eprintln!("OSC receive error: {}", e);
+                    continue;
+                }
+            };
+            let messages = match parse(&buffer[..size]) {
+                Ok(messages) => messages,
+                Err(e) => {
+                    eprintln!("{} from {}", e, sender);
+                    continue;
+                }
+            };
+            for action in messages.iter().filter_map(to_action) {
+                // The receiver only goes away when the window closes
+                if tx.send(action).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(address: &str, args: Vec<Arg>) -> Message {
+        Message {
+            address: address.to_owned(),
+            args,
+        }
+    }
+
+    /// A bundle with an empty time tag around `elements`.
+    fn bundle(elements: &[&[u8]]) -> Vec<u8> {
+        let mut packet = b"#bundle\0\0\0\0\0\0\0\0\x01".to_vec();
+        for element in elements {
+            packet.extend_from_slice(&(element.len() as i32).to_be_bytes());
+            packet.extend_from_slice(element);
+        }
+        packet
+    }
+
+    #[test]
+    fn strings_are_padded_to_4_bytes() {
+        // A 4 byte string still gets a full word of padding for its terminator
+        let packet = b"/abc\0\0\0\0,ss\0abc\0hello\0\0\0";
+        assert_eq!(
+            parse(packet).unwrap(),
+            vec![message(
+                "/abc",
+                vec![Arg::Str("abc".into()), Arg::Str("hello".into())]
+            )]
+        );
+    }
+
+    #[test]
+    fn blobs_skip_their_padding() {
+        let packet = b"/b\0\0,bi\0\0\0\0\x0512345\0\0\0\0\0\0\x07";
+        assert_eq!(
+            parse(packet).unwrap(),
+            vec![message(
+                "/b",
+                vec![Arg::Blob(b"12345".to_vec()), Arg::Int(7)]
+            )]
+        );
+    }
+
+    #[test]
+    fn nested_bundles_are_flattened() {
+        let reset = b"/reset\0\0,i\0\0\0\0\0\x01";
+        let speed = b"/x\0\0,f\0\0\x3f\xc0\0\0";
+        let inner = bundle(&[speed]);
+        let packet = bundle(&[reset, &inner]);
+        assert_eq!(
+            parse(&packet).unwrap(),
+            vec![
+                message("/reset", vec![Arg::Int(1)]),
+                message("/x", vec![Arg::Float(1.5)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_type_tags_mean_no_arguments() {
+        assert_eq!(
+            parse(b"/center\0").unwrap(),
+            vec![message("/center", vec![])]
+        );
+    }
+
+    #[test]
+    fn malformed_packets_are_rejected() {
+        // Unterminated address, an int cut short, a blob longer than the packet and a bundle
+        // element running past the end
+        assert!(parse(b"/abc").is_err());
+        assert!(parse(b"/a\0\0,i\0\0\0\0").is_err());
+        assert!(parse(b"/a\0\0,b\0\0\0\0\0\x08abcd").is_err());
+        let mut truncated = bundle(&[b"/a\0\0,\0\0\0"]);
+        truncated.truncate(truncated.len() - 2);
+        assert!(parse(&truncated).is_err());
+        assert!(parse(b"abc\0").is_err());
+        assert!(parse(b"/a\0\0i\0\0\0\0\0\0\x01").is_err());
+
+        let e = parse(b"/a\0\0,x\0\0").unwrap_err();
+        assert!(e.to_string().contains("unsupported type tag"), "{}", e);
+    }
+
+    #[test]
+    fn encode_round_trips() {
+        let sent = message(
+            "/species/0/speed",
+            vec![
+                Arg::Float(3.5),
+                Arg::Int(-2),
+                Arg::Int(1 << 40),
+                Arg::Str("abcd".into()),
+                Arg::Blob(vec![1, 2, 3]),
+                Arg::Bool(true),
+                Arg::Empty,
+            ],
+        );
+        assert_eq!(parse(&encode(&sent)).unwrap(), vec![sent]);
+    }
+
+    #[test]
+    fn messages_map_to_actions() {
+        let action = |address: &str, args: Vec<Arg>| to_action(&message(address, args));
+
+        assert!(action("/reset", vec![Arg::Int(0)]).is_none());
+        assert!(matches!(action("/reset", vec![]), Some(Action::Reset)));
+        assert!(matches!(
+            action("/screenshot", vec![Arg::Float(1.0)]),
+            Some(Action::Screenshot)
+        ));
+        assert!(matches!(
+            action("/record", vec![]),
+            Some(Action::Record(None))
+        ));
+        assert!(matches!(
+            action("/record", vec![Arg::Int(1)]),
+            Some(Action::Record(Some(true)))
+        ));
+        assert!(matches!(
+            action("/center", vec![Arg::Bool(false)]),
+            Some(Action::MoveToCenter(Some(false)))
+        ));
+        match action("/species/0/speed", vec![Arg::Float(3.5)]) {
+            Some(Action::SetParam(path, value)) => {
+                assert_eq!(path, "species.0.speed");
+                assert_eq!(value, 3.5);
+            }
+            other => panic!("{:?}", other),
+        }
+        // A parameter needs a value
+        assert!(action("/world/decay", vec![]).is_none());
+    }
+
+    #[test]
+    fn command_line_values_are_floats_or_strings() {
+        let values = ["3.5".to_owned(), "-1".to_owned(), "on".to_owned()];
+        assert_eq!(
+            message_from_args("/a", &values).args,
+            vec![Arg::Float(3.5), Arg::Float(-1.0), Arg::Str("on".into())]
+        );
+    }
+}
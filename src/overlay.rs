@@ -0,0 +1,77 @@
+use std::time::Instant;
+
+use egui_wgpu_backend::{RenderPass, ScreenDescriptor};
+use egui_winit_platform::{Platform, PlatformDescriptor};
+use winit::event::Event;
+use winit::window::Window;
+
+/// Immediate mode UI drawn on top of the simulation. Owns the egui context and
+/// the render pass that draws it onto the swapchain after the copy pass.
+pub struct Overlay {
+    platform: Platform,
+    render_pass: RenderPass,
+    start_time: Instant,
+}
+
+impl Overlay {
+    pub fn new(device: &wgpu::Device, window: &Window, format: wgpu::TextureFormat) -> Self {
+        let size = window.inner_size();
+        let platform = Platform::new(PlatformDescriptor {
+            physical_width: size.width,
+            physical_height: size.height,
+            scale_factor: window.scale_factor(),
+            font_definitions: egui::FontDefinitions::default(),
+            style: Default::default(),
+        });
+        Overlay {
+            platform,
+            render_pass: RenderPass::new(device, format, 1),
+            start_time: Instant::now(),
+        }
+    }
+
+    pub fn handle_event<T>(&mut self, event: &Event<T>) {
+        self.platform.handle_event(event);
+    }
+
+    /// Whether egui wants this event for itself, e.g. key presses while typing in a text field.
+    pub fn captures_event<T>(&self, event: &Event<T>) -> bool {
+        self.platform.captures_event(event)
+    }
+
+    pub fn begin_frame(&mut self) -> egui::CtxRef {
+        self.platform
+            .update_time(self.start_time.elapsed().as_secs_f64());
+        self.platform.begin_frame();
+        self.platform.context()
+    }
+
+    /// Finishes the egui frame and records a render pass that draws it over `view`.
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        window: &Window,
+    ) {
+        let (_output, shapes) = self.platform.end_frame(Some(window));
+        let ctx = self.platform.context();
+        let paint_jobs = ctx.tessellate(shapes);
+
+        let size = window.inner_size();
+        let screen_descriptor = ScreenDescriptor {
+            physical_width: size.width,
+            physical_height: size.height,
+            scale_factor: window.scale_factor() as f32,
+        };
+        self.render_pass
+            .update_texture(device, queue, &ctx.texture());
+        self.render_pass.update_user_textures(device, queue);
+        self.render_pass
+            .update_buffers(device, queue, &paint_jobs, &screen_descriptor);
+        self.render_pass
+            .execute(encoder, view, &paint_jobs, &screen_descriptor, None)
+            .expect("error rendering overlay");
+    }
+}
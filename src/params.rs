@@ -0,0 +1,446 @@
+use serde::{Deserialize, Serialize};
+
+/// Number of species. The world holds a trail plane per species, so memory grows with it.
+/// Shaders get it as a constant, every per-species table is sized by it.
+pub const NUM_SPECIES: usize = 4;
+
+/// Movement parameters of a single species, mirrored by `MoveParams` in `move_slimes.wgsl`.
+/// Padded to 32 bytes because uniform arrays need a 16 byte aligned stride.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
+pub struct MoveParams {
+    pub move_speed: f32,
+    pub turn_speed: f32,
+    pub sensor_distance: f32,
+    pub sensor_angle: f32,
+    pub deposit_amount: f32,
+    #[serde(skip)]
+    _pad: [f32; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SpeciesParams {
+    pub species: [MoveParams; NUM_SPECIES],
+}
+
+impl MoveParams {
+    pub const fn new(
+        move_speed: f32,
+        turn_speed: f32,
+        sensor_distance: f32,
+        sensor_angle: f32,
+        deposit_amount: f32,
+    ) -> Self {
+        MoveParams {
+            move_speed,
+            turn_speed,
+            sensor_distance,
+            sensor_angle,
+            deposit_amount,
+            _pad: [0.0; 3],
+        }
+    }
+}
+
+/// Starting parameters, repeated for species past the fourth.
+const DEFAULT_SPECIES: [MoveParams; 4] = [
+    MoveParams::new(5.0, 0.4, 6.0, 0.5, 0.1),
+    MoveParams::new(5.0, 0.4, 6.0, 0.5, 0.1),
+    MoveParams::new(2.1, 0.3, 45.0, 0.82, 0.2),
+    MoveParams::new(0.2, 0.4, 5.0, 0.5, 0.2),
+];
+
+impl Default for SpeciesParams {
+    fn default() -> Self {
+        SpeciesParams {
+            species: std::array::from_fn(|i| DEFAULT_SPECIES[i % DEFAULT_SPECIES.len()]),
+        }
+    }
+}
+
+/// How each species senses the trail, mirrored by `Interactions` in `move_slimes.wgsl`. Row `i`
+/// weights every species' trail channel as sensed by species `i`. Positive weights attract,
+/// negative weights repel.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
+pub struct Interactions {
+    pub weights: [[f32; NUM_SPECIES]; NUM_SPECIES],
+}
+
+impl Default for Interactions {
+    /// Every species follows its own trail, and species 0 and 1 avoid each other.
+    fn default() -> Self {
+        let mut weights = [[0.0; NUM_SPECIES]; NUM_SPECIES];
+        for (i, row) in weights.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        weights[0][1] = -1.0;
+        weights[1][0] = -1.0;
+        Interactions { weights }
+    }
+}
+
+/// Trail diffusion and decay settings used by `update_world.wgsl`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct WorldParams {
+    /// Fraction of the trail kept each frame.
+    pub decay: f32,
+    /// Radius of the box blur in pixels, 0 disables diffusion.
+    pub blur_radius: i32,
+    /// Trail values below this are snapped to zero.
+    pub cutoff: f32,
+}
+
+impl Default for WorldParams {
+    fn default() -> Self {
+        WorldParams {
+            decay: 0.95,
+            blur_radius: 1,
+            cutoff: 0.0005,
+        }
+    }
+}
+
+/// Colouring used by `world_to_tex.wgsl`. Each species' gradient is multiplied by its tint,
+/// `rgb` being the colour and `a` the intensity.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
+pub struct Palette {
+    pub tints: [[f32; 4]; NUM_SPECIES],
+    pub brightness: f32,
+    #[serde(skip)]
+    _pad: [f32; 3],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            tints: [[1.0; 4]; NUM_SPECIES],
+            brightness: 0.8,
+            _pad: [0.0; 3],
+        }
+    }
+}
+
+/// Optional energy model, mirrored by `LifecycleParams` in `slimes.wgsl`. Slimes lose energy
+/// every frame, regain it from their own species' trail, die when it runs out and split in two
+/// above `split_energy`. A slime breaks even on a trail of `drain / gain`, the defaults put that
+/// near the average trail of a full world so slimes between the strands die and those on them
+/// split.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LifecycleParams {
+    /// Non-zero to enable, a `u32` because it is read by the shaders.
+    pub enabled: u32,
+    pub drain: f32,
+    pub gain: f32,
+    /// At least 0.5, see `lifecycle.wgsl` and `SimParams::clamp`.
+    pub split_energy: f32,
+}
+
+impl Default for LifecycleParams {
+    fn default() -> Self {
+        LifecycleParams {
+            enabled: 0,
+            drain: 0.002,
+            gain: 0.01,
+            split_energy: 0.8,
+        }
+    }
+}
+
+/// Per-slime genes, mirrored by `GeneticsParams` in `slimes.wgsl`. Genes only change when
+/// slimes split, so evolution needs the lifecycle model too.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GeneticsParams {
+    /// Non-zero to move by each slime's genes instead of its species' parameters.
+    pub enabled: u32,
+    /// Largest relative change of each gene passed on to a new slime.
+    pub mutation: f32,
+    #[serde(skip)]
+    _pad: [f32; 2],
+}
+
+impl Default for GeneticsParams {
+    fn default() -> Self {
+        GeneticsParams {
+            enabled: 0,
+            mutation: 0.05,
+            _pad: [0.0; 2],
+        }
+    }
+}
+
+/// Food source behaviour used by `food.wgsl`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FoodParams {
+    /// Chemoattractant a full source adds to every trail channel per frame.
+    pub emission: f32,
+    /// Nutrients used up per unit of trail the slimes deposit on a source.
+    pub consumption: f32,
+    /// Radius of a source in pixels. Raising it can make sources overlap, see `food::load`.
+    pub radius: i32,
+}
+
+impl Default for FoodParams {
+    fn default() -> Self {
+        FoodParams {
+            emission: 0.05,
+            consumption: 0.001,
+            radius: 4,
+        }
+    }
+}
+
+/// Settings for extracting the transport network from the trail, see `network.rs`.
+#[derive(Debug, Clone, Copy)]
+pub struct NetworkParams {
+    /// Summed trail strength a pixel needs to count as part of the network.
+    pub threshold: f32,
+    /// Dead ends shorter than this many pixels are dropped as thinning artifacts.
+    pub min_spur_length: f32,
+}
+
+impl Default for NetworkParams {
+    fn default() -> Self {
+        NetworkParams {
+            threshold: 0.5,
+            min_spur_length: 10.0,
+        }
+    }
+}
+
+/// How slimes are split between the species when they are spawned or rebalanced. Relative
+/// weights, so `[1, 1, 0, 0]` is half species 0 and half species 1.
+#[derive(Debug, Clone, Copy)]
+pub struct SwarmParams {
+    pub species_weights: [f32; NUM_SPECIES],
+}
+
+impl Default for SwarmParams {
+    fn default() -> Self {
+        SwarmParams {
+            species_weights: std::array::from_fn(|i| if i < 2 { 1.0 } else { 0.0 }),
+        }
+    }
+}
+
+/// Everything that can be tuned while the simulation runs.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SimParams {
+    pub species: SpeciesParams,
+    pub interactions: Interactions,
+    pub world: WorldParams,
+    pub palette: Palette,
+    pub swarm: SwarmParams,
+    pub lifecycle: LifecycleParams,
+    pub genetics: GeneticsParams,
+    pub food: FoodParams,
+    pub network: NetworkParams,
+}
+
+/// A parameter reached by a path such as `species.0.move_speed`.
+enum Field<'a> {
+    F32(&'a mut f32),
+    I32(&'a mut i32),
+    U32(&'a mut u32),
+}
+
+impl Field<'_> {
+    fn get(&self) -> f32 {
+        match self {
+            Field::F32(v) => **v,
+            Field::I32(v) => **v as f32,
+            Field::U32(v) => **v as f32,
+        }
+    }
+
+    /// Integer parameters are rounded, and unsigned ones (the `enabled` switches) clamped at 0.
+    /// Ranges specific to a parameter are up to `SimParams::clamp`.
+    fn set(&mut self, value: f32) {
+        match self {
+            Field::F32(v) => **v = value,
+            Field::I32(v) => **v = value.round() as i32,
+            Field::U32(v) => **v = value.round().max(0.0) as u32,
+        }
+    }
+}
+
+const MOVE_FIELDS: [&str; 5] = [
+    "move_speed",
+    "turn_speed",
+    "sensor_distance",
+    "sensor_angle",
+    "deposit_amount",
+];
+const SCALAR_PATHS: [&str; 15] = [
+    "world.decay",
+    "world.blur_radius",
+    "world.cutoff",
+    "palette.brightness",
+    "lifecycle.enabled",
+    "lifecycle.drain",
+    "lifecycle.gain",
+    "lifecycle.split_energy",
+    "genetics.enabled",
+    "genetics.mutation",
+    "food.emission",
+    "food.consumption",
+    "food.radius",
+    "network.threshold",
+    "network.min_spur_length",
+];
+
+/// Parses a species or channel index below `len`.
+fn index(part: Option<&str>, len: usize) -> Option<usize> {
+    part?.parse().ok().filter(|&i| i < len)
+}
+
+impl SimParams {
+    /// Every parameter path `get` and `set` accept, without the short aliases.
+    pub fn paths() -> Vec<String> {
+        let mut paths = Vec::new();
+        for s in 0..NUM_SPECIES {
+            paths.extend(MOVE_FIELDS.iter().map(|f| format!("species.{}.{}", s, f)));
+        }
+        for i in 0..NUM_SPECIES {
+            paths.extend((0..NUM_SPECIES).map(|j| format!("interactions.{}.{}", i, j)));
+        }
+        paths.extend(SCALAR_PATHS.iter().map(|p| p.to_string()));
+        for s in 0..NUM_SPECIES {
+            paths.extend((0..4).map(|c| format!("palette.tints.{}.{}", s, c)));
+        }
+        paths.extend((0..NUM_SPECIES).map(|s| format!("swarm.species_weights.{}", s)));
+        paths
+    }
+
+    /// The parameter at `path`, dot separated. Species fields also take the short names the
+    /// HUD uses (`speed`, `turn`, `sense`, `angle`, `deposit`), and tint channels `r`, `g`, `b`
+    /// and `a`.
+    fn field(&mut self, path: &str) -> Option<Field<'_>> {
+        let mut parts = path.split('.');
+        let field = match parts.next()? {
+            "species" => {
+                let p = &mut self.species.species[index(parts.next(), NUM_SPECIES)?];
+                Field::F32(match parts.next()? {
+                    "move_speed" | "speed" => &mut p.move_speed,
+                    "turn_speed" | "turn" => &mut p.turn_speed,
+                    "sensor_distance" | "sense" => &mut p.sensor_distance,
+                    "sensor_angle" | "angle" => &mut p.sensor_angle,
+                    "deposit_amount" | "deposit" => &mut p.deposit_amount,
+                    _ => return None,
+                })
+            }
+            "interactions" => {
+                let row = index(parts.next(), NUM_SPECIES)?;
+                let column = index(parts.next(), NUM_SPECIES)?;
+                Field::F32(&mut self.interactions.weights[row][column])
+            }
+            "world" => match parts.next()? {
+                "decay" => Field::F32(&mut self.world.decay),
+                "blur_radius" => Field::I32(&mut self.world.blur_radius),
+                "cutoff" => Field::F32(&mut self.world.cutoff),
+                _ => return None,
+            },
+            "palette" => match parts.next()? {
+                "brightness" => Field::F32(&mut self.palette.brightness),
+                "tints" => {
+                    let tint = &mut self.palette.tints[index(parts.next(), NUM_SPECIES)?];
+                    let channel = match parts.next()? {
+                        "r" => 0,
+                        "g" => 1,
+                        "b" => 2,
+                        "a" => 3,
+                        c => c.parse().ok().filter(|&c| c < 4)?,
+                    };
+                    Field::F32(&mut tint[channel])
+                }
+                _ => return None,
+            },
+            "swarm" => match parts.next()? {
+                "species_weights" => {
+                    Field::F32(&mut self.swarm.species_weights[index(parts.next(), NUM_SPECIES)?])
+                }
+                _ => return None,
+            },
+            "lifecycle" => match parts.next()? {
+                "enabled" => Field::U32(&mut self.lifecycle.enabled),
+                "drain" => Field::F32(&mut self.lifecycle.drain),
+                "gain" => Field::F32(&mut self.lifecycle.gain),
+                "split_energy" => Field::F32(&mut self.lifecycle.split_energy),
+                _ => return None,
+            },
+            "genetics" => match parts.next()? {
+                "enabled" => Field::U32(&mut self.genetics.enabled),
+                "mutation" => Field::F32(&mut self.genetics.mutation),
+                _ => return None,
+            },
+            "food" => match parts.next()? {
+                "emission" => Field::F32(&mut self.food.emission),
+                "consumption" => Field::F32(&mut self.food.consumption),
+                "radius" => Field::I32(&mut self.food.radius),
+                _ => return None,
+            },
+            "network" => match parts.next()? {
+                "threshold" => Field::F32(&mut self.network.threshold),
+                "min_spur_length" => Field::F32(&mut self.network.min_spur_length),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        // Trailing parts mean a typo, don't silently set something else
+        match parts.next() {
+            Some(_) => None,
+            None => Some(field),
+        }
+    }
+
+    /// The value of the parameter at `path`, see `paths`.
+    pub fn get(&self, path: &str) -> Option<f32> {
+        let mut copy = *self;
+        let value = copy.field(path)?.get();
+        Some(value)
+    }
+
+    /// Sets the parameter at `path`, pulled back into the range the shaders expect, see
+    /// `clamp`. The caller uploads the parameters afterwards.
+    pub fn set(&mut self, path: &str, value: f32) -> Result<(), UnknownParam> {
+        match self.field(path) {
+            Some(mut field) => {
+                field.set(value);
+                self.clamp();
+                Ok(())
+            }
+            None => Err(UnknownParam(path.to_owned())),
+        }
+    }
+
+    /// Keeps the parameters the shaders make assumptions about in range. Applied to everything
+    /// set by path (timelines, audio, OSC and the HTTP API) and to loaded presets, the parameter
+    /// panel's sliders already stay within these bounds.
+    pub fn clamp(&mut self) {
+        // Splits are only race free above 0.5, see `lifecycle.wgsl`
+        self.lifecycle.split_energy = self.lifecycle.split_energy.max(0.5);
+        self.lifecycle.enabled = self.lifecycle.enabled.min(1);
+        self.genetics.enabled = self.genetics.enabled.min(1);
+        self.world.blur_radius = self.world.blur_radius.max(0);
+        self.food.radius = self.food.radius.max(0);
+    }
+}
+
+/// A parameter path that doesn't name a parameter.
+#[derive(Debug)]
+pub struct UnknownParam(pub String);
+
+impl std::fmt::Display for UnknownParam {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unknown parameter: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownParam {}
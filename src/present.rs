@@ -0,0 +1,200 @@
+//! How the world texture is scaled to the window. The world is usually larger than the window,
+//! and sampling it at single texels aliases, so the filter is selectable. The area and bicubic
+//! filters read the mip chain of the world texture, which is rebuilt after every frame while
+//! one of them is in use.
+
+use std::fmt;
+use std::num::NonZeroU32;
+use std::str::FromStr;
+
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+
+use crate::layouts;
+use crate::shaders::{reload, ShaderError, ShaderErrors, ShaderLoader};
+use crate::sizing::{SimSize, WORLD_WORKGROUP_SIZE};
+use crate::DOWNSAMPLE_SHADER;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    /// The closest texel, sharp but aliased when shrinking.
+    Nearest,
+    /// Bilinear between the four closest texels.
+    Linear,
+    /// The average of the texels under each pixel, from the mip chain.
+    Area,
+    /// Catmull-Rom over the sixteen closest texels of the nearest larger mip level.
+    Bicubic,
+}
+
+impl Filter {
+    pub const ALL: [Filter; 4] = [
+        Filter::Nearest,
+        Filter::Linear,
+        Filter::Area,
+        Filter::Bicubic,
+    ];
+
+    /// The filter after this one, for cycling through them with a key.
+    pub fn next(self) -> Filter {
+        let index = Filter::ALL.iter().position(|&f| f == self).unwrap();
+        Filter::ALL[(index + 1) % Filter::ALL.len()]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Filter::Nearest => "nearest",
+            Filter::Linear => "linear",
+            Filter::Area => "area",
+            Filter::Bicubic => "bicubic",
+        }
+    }
+
+    /// Whether the filter reads the smaller mip levels.
+    pub fn uses_mips(self) -> bool {
+        matches!(self, Filter::Area | Filter::Bicubic)
+    }
+
+    /// Scales a captured frame to `width`, keeping its aspect ratio, with the closest CPU
+    /// counterpart of the filter.
+    pub fn resize(self, image: &RgbaImage, width: u32) -> RgbaImage {
+        let width = width.clamp(1, image.width());
+        let height = (image.height() as u64 * width as u64 / image.width() as u64).max(1) as u32;
+        match self {
+            Filter::Nearest => imageops::resize(image, width, height, FilterType::Nearest),
+            Filter::Linear => imageops::resize(image, width, height, FilterType::Triangle),
+            Filter::Area => imageops::thumbnail(image, width, height),
+            Filter::Bicubic => imageops::resize(image, width, height, FilterType::CatmullRom),
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Filter::ALL
+            .into_iter()
+            .find(|f| f.name() == s)
+            .ok_or_else(|| format!("expected nearest, linear, area or bicubic, got {}", s))
+    }
+}
+
+/// Uniform of `shader.wgsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PresentConfig {
+    pub filter: u32,
+    _pad: [u32; 3],
+}
+
+impl PresentConfig {
+    pub fn new(filter: Filter) -> Self {
+        PresentConfig {
+            filter: Filter::ALL.iter().position(|&f| f == filter).unwrap() as u32,
+            _pad: [0; 3],
+        }
+    }
+}
+
+/// Rebuilds the mip chain of the world texture, each level averaged from the one before.
+pub struct MipChain {
+    pipeline_layout: wgpu::PipelineLayout,
+    pipeline: wgpu::ComputePipeline,
+    /// Reads level `i` and writes level `i + 1`, with the size of level `i + 1`.
+    levels: Vec<(wgpu::BindGroup, (u32, u32))>,
+}
+
+impl MipChain {
+    /// Panics if the shader fails to load, like the simulation's shaders.
+    pub fn new(
+        device: &wgpu::Device,
+        shader_loader: &ShaderLoader,
+        texture: &wgpu::Texture,
+        size: &SimSize,
+    ) -> Self {
+        let bind_group_layout = layouts::DOWNSAMPLE.create(device);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline =
+            load(device, shader_loader, &pipeline_layout).unwrap_or_else(|e| panic!("{}", e));
+
+        let level_view = |level| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                base_mip_level: level,
+                mip_level_count: NonZeroU32::new(1),
+                ..Default::default()
+            })
+        };
+        let levels = (1..size.mip_levels())
+            .map(|level| {
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: None,
+                    layout: &bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&level_view(level - 1)),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::TextureView(&level_view(level)),
+                        },
+                    ],
+                });
+                let (width, height) = size.world;
+                (
+                    bind_group,
+                    ((width >> level).max(1), (height >> level).max(1)),
+                )
+            })
+            .collect();
+        MipChain {
+            pipeline_layout,
+            pipeline,
+            levels,
+        }
+    }
+
+    /// Records the passes that fill every level below the first.
+    pub fn encode(&self, encoder: &mut wgpu::CommandEncoder) {
+        for (bind_group, (width, height)) in &self.levels {
+            let mut cpass = encoder.begin_compute_pass(&Default::default());
+            cpass.set_pipeline(&self.pipeline);
+            cpass.set_bind_group(0, bind_group, &[]);
+            cpass.dispatch(
+                width.div_ceil(WORLD_WORKGROUP_SIZE),
+                height.div_ceil(WORLD_WORKGROUP_SIZE),
+                1,
+            );
+        }
+    }
+
+    pub fn reload_shader(
+        &mut self,
+        device: &wgpu::Device,
+        shader_loader: &ShaderLoader,
+        errors: &mut ShaderErrors,
+    ) {
+        reload(&mut self.pipeline, DOWNSAMPLE_SHADER, errors, || {
+            load(device, shader_loader, &self.pipeline_layout)
+        });
+    }
+}
+
+fn load(
+    device: &wgpu::Device,
+    shader_loader: &ShaderLoader,
+    layout: &wgpu::PipelineLayout,
+) -> Result<wgpu::ComputePipeline, ShaderError> {
+    shader_loader.load_pipeline(DOWNSAMPLE_SHADER, device, layout, layouts::DOWNSAMPLE_MIPS)
+}
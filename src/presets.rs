@@ -0,0 +1,101 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::params::{
+    FoodParams, GeneticsParams, Interactions, LifecycleParams, MoveParams, Palette, SimParams,
+    WorldParams, NUM_SPECIES,
+};
+
+const PRESET_DIR: &str = "presets";
+
+/// Everything that can be tuned from the parameter panel, saved as `presets/<name>.toml`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Preset {
+    pub world: WorldParams,
+    pub palette: Palette,
+    pub species: [MoveParams; NUM_SPECIES],
+    #[serde(default)]
+    pub interactions: Interactions,
+    /// Missing from presets saved before the lifecycle model existed.
+    #[serde(default)]
+    pub lifecycle: LifecycleParams,
+    #[serde(default)]
+    pub genetics: GeneticsParams,
+    #[serde(default)]
+    pub food: FoodParams,
+}
+
+impl Preset {
+    pub fn from_params(params: &SimParams) -> Self {
+        Preset {
+            world: params.world,
+            palette: params.palette,
+            species: params.species.species,
+            interactions: params.interactions,
+            lifecycle: params.lifecycle,
+            genetics: params.genetics,
+            food: params.food,
+        }
+    }
+
+    /// Overwrites the parameters the preset covers, leaving the rest of `params` alone. Values
+    /// out of range in the file are clamped.
+    pub fn apply(&self, params: &mut SimParams) {
+        params.world = self.world;
+        params.palette = self.palette;
+        params.species.species = self.species;
+        params.interactions = self.interactions;
+        params.lifecycle = self.lifecycle;
+        params.genetics = self.genetics;
+        params.food = self.food;
+        params.clamp();
+    }
+}
+
+/// Checks that `name` stays a single file name inside the preset directory.
+fn check_name(name: &str) -> Result<(), Box<dyn Error>> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.contains("..") {
+        return Err(format!("invalid preset name {:?}", name).into());
+    }
+    Ok(())
+}
+
+fn preset_path(name: &str) -> Result<PathBuf, Box<dyn Error>> {
+    check_name(name)?;
+    Ok(PathBuf::from(PRESET_DIR).join(format!("{}.toml", name)))
+}
+
+/// Thumbnail saved next to a preset by `explore`.
+pub fn thumbnail_path(name: &str) -> PathBuf {
+    PathBuf::from(PRESET_DIR).join(format!("{}.png", name))
+}
+
+pub fn save(name: &str, preset: &Preset) -> Result<(), Box<dyn Error>> {
+    let path = preset_path(name)?;
+    std::fs::create_dir_all(PRESET_DIR)?;
+    std::fs::write(path, toml::to_string(preset)?)?;
+    Ok(())
+}
+
+pub fn load(name: &str) -> Result<Preset, Box<dyn Error>> {
+    let text = std::fs::read_to_string(preset_path(name)?)?;
+    Ok(toml::from_str(&text)?)
+}
+
+/// Names of all presets in the preset directory, sorted.
+pub fn list() -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(PRESET_DIR)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.extension().is_some_and(|ext| ext == "toml"))
+                .filter_map(|p| Some(p.file_stem()?.to_str()?.to_owned()))
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
@@ -0,0 +1,309 @@
+//! Checks the resource bindings a shader declares against the layouts in `layouts`, so a struct
+//! that drifted out of sync with its Rust counterpart fails to load instead of reading garbage.
+
+use naga::{
+    ArraySize, GlobalVariable, ImageClass, Module, StorageAccess, StorageClass, StorageFormat,
+    Type, TypeInner,
+};
+
+use crate::layouts::{HostLayout, Interface, Resource};
+
+/// Returns a description of every binding in `module` that doesn't match `interface`.
+pub fn validate(module: &Module, interface: Interface) -> Result<(), String> {
+    let mut errors = Vec::new();
+    for (_, var) in module.global_variables.iter() {
+        let binding = match var.binding {
+            Some(ref binding) => binding,
+            None => continue,
+        };
+        let name = var.name.as_deref().unwrap_or("<unnamed>");
+        let expected = interface.get(binding.group as usize).and_then(|group| {
+            group
+                .bindings
+                .iter()
+                .find(|b| b.binding == binding.binding)
+                .map(|b| (group, b))
+        });
+        let (group, expected) = match expected {
+            Some(found) => found,
+            None => {
+                errors.push(format!(
+                    "`{}` is bound at group {} binding {}, which the pipeline layout doesn't have",
+                    name, binding.group, binding.binding
+                ));
+                continue;
+            }
+        };
+        if let Err(e) = check_binding(module, var, &expected.resource) {
+            errors.push(format!(
+                "`{}` (group {} binding {}, {} group): {}",
+                name, binding.group, binding.binding, group.name, e
+            ));
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+fn check_binding(module: &Module, var: &GlobalVariable, expected: &Resource) -> Result<(), String> {
+    let ty = &module.types[var.ty];
+    match (expected, var.class) {
+        (Resource::Uniform(layout), StorageClass::Uniform) => check_struct(module, ty, layout),
+        (Resource::Storage { read_only, element }, StorageClass::Storage { access }) => {
+            let shader_read_only = !access.contains(StorageAccess::STORE);
+            if shader_read_only != *read_only {
+                return Err(format!(
+                    "declared as {} storage, but the layout binds it as {}",
+                    access_name(shader_read_only),
+                    access_name(*read_only)
+                ));
+            }
+            check_runtime_array(module, ty, element)
+        }
+        (Resource::StorageTexture(format), StorageClass::Handle) => match ty.inner {
+            TypeInner::Image {
+                class: ImageClass::Storage { format: f, .. },
+                ..
+            } => {
+                if storage_format(*format) == Some(f) {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "storage texture format is {:?}, the layout uses {:?}",
+                        f, format
+                    ))
+                }
+            }
+            _ => Err(mismatch(ty, "a storage texture")),
+        },
+        (Resource::Texture, StorageClass::Handle) => match ty.inner {
+            TypeInner::Image {
+                class: ImageClass::Sampled { multi: false, .. },
+                ..
+            } => Ok(()),
+            _ => Err(mismatch(ty, "a sampled texture")),
+        },
+        (Resource::Sampler { .. }, StorageClass::Handle) => match ty.inner {
+            TypeInner::Sampler { comparison: false } => Ok(()),
+            _ => Err(mismatch(ty, "a sampler")),
+        },
+        (expected, class) => Err(format!(
+            "declared as {:?}, but the layout binds {}",
+            class,
+            resource_name(expected)
+        )),
+    }
+}
+
+fn access_name(read_only: bool) -> &'static str {
+    if read_only {
+        "read only"
+    } else {
+        "read_write"
+    }
+}
+
+fn resource_name(resource: &Resource) -> String {
+    match resource {
+        Resource::Uniform(layout) => format!("a uniform buffer of `{}`", layout.name),
+        Resource::Storage { element, .. } => {
+            format!("a storage buffer of `{}` elements", element.name)
+        }
+        Resource::StorageTexture(_) => String::from("a storage texture"),
+        Resource::Texture => String::from("a sampled texture"),
+        Resource::Sampler { .. } => String::from("a sampler"),
+    }
+}
+
+fn type_name(ty: &Type) -> String {
+    ty.name.clone().unwrap_or_else(|| format!("{:?}", ty.inner))
+}
+
+fn mismatch(ty: &Type, expected: &str) -> String {
+    format!(
+        "has type `{}`, but the layout binds {}",
+        type_name(ty),
+        expected
+    )
+}
+
+fn storage_format(format: wgpu::TextureFormat) -> Option<StorageFormat> {
+    Some(match format {
+        wgpu::TextureFormat::Rgba8Unorm => StorageFormat::Rgba8Unorm,
+        wgpu::TextureFormat::Rgba16Float => StorageFormat::Rgba16Float,
+        wgpu::TextureFormat::Rgba32Float => StorageFormat::Rgba32Float,
+        wgpu::TextureFormat::R32Float => StorageFormat::R32Float,
+        _ => return None,
+    })
+}
+
+/// A storage buffer is a struct whose last member is a runtime sized array of `element`.
+fn check_runtime_array(module: &Module, ty: &Type, element: &HostLayout) -> Result<(), String> {
+    let array = match ty.inner {
+        TypeInner::Struct { ref members, .. } => members.last().map(|m| &module.types[m.ty]),
+        _ => None,
+    };
+    match array.map(|a| &a.inner) {
+        Some(&TypeInner::Array {
+            base,
+            size: ArraySize::Dynamic,
+            stride,
+        }) => {
+            if stride as usize != element.size {
+                return Err(format!(
+                    "array stride is {} bytes, but `{}` is {} bytes",
+                    stride, element.name, element.size
+                ));
+            }
+            if element.fields.is_empty() {
+                Ok(())
+            } else {
+                check_struct(module, &module.types[base], element)
+            }
+        }
+        _ => Err(mismatch(
+            ty,
+            &format!("a runtime sized array of `{}`", element.name),
+        )),
+    }
+}
+
+/// Compares a WGSL struct with the Rust struct it is filled from: same size, and the same
+/// fields at the same offsets. WGSL members named `pad*` are padding and not compared.
+fn check_struct(module: &Module, ty: &Type, layout: &HostLayout) -> Result<(), String> {
+    let (members, span) = match ty.inner {
+        TypeInner::Struct {
+            ref members, span, ..
+        } => (members, span),
+        _ => return Err(mismatch(ty, &format!("`{}`", layout.name))),
+    };
+    let shader_name = type_name(ty);
+    if span as usize != layout.size {
+        return Err(format!(
+            "`{}` is {} bytes, but `{}` is {} bytes",
+            shader_name, span, layout.name, layout.size
+        ));
+    }
+    let members: Vec<_> = members
+        .iter()
+        .filter(|m| !m.name.as_deref().is_some_and(|n| n.starts_with("pad")))
+        .collect();
+    if members.len() != layout.fields.len() {
+        return Err(format!(
+            "`{}` has {} fields, but `{}` has {}",
+            shader_name,
+            members.len(),
+            layout.name,
+            layout.fields.len()
+        ));
+    }
+    for (member, field) in members.iter().zip(layout.fields) {
+        let member_name = member.name.as_deref().unwrap_or("<unnamed>");
+        if member_name != field.name {
+            return Err(format!(
+                "field `{}.{}` is where `{}.{}` is expected, check the field order",
+                shader_name, member_name, layout.name, field.name
+            ));
+        }
+        let member_ty = &module.types[member.ty];
+        let size = member_ty.inner.span(&module.constants) as usize;
+        if member.offset as usize != field.offset || size != field.size {
+            return Err(format!(
+                "`{}.{}` is {} bytes at offset {}, but `{}.{}` is {} bytes at offset {}",
+                shader_name,
+                member_name,
+                size,
+                member.offset,
+                layout.name,
+                field.name,
+                field.size,
+                field.offset
+            ));
+        }
+        if let Some(inner) = field.inner {
+            let inner_ty = match member_ty.inner {
+                TypeInner::Array { base, stride, .. } => {
+                    if stride as usize != inner.size {
+                        return Err(format!(
+                            "`{}.{}` has a stride of {} bytes, but `{}` is {} bytes",
+                            shader_name, member_name, stride, inner.name, inner.size
+                        ));
+                    }
+                    &module.types[base]
+                }
+                _ => member_ty,
+            };
+            check_struct(module, inner_ty, inner)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layouts::{self, Binding, GroupLayout};
+    use crate::shaders::ShaderDir;
+
+    const SHADER_INTERFACES: [(&str, Interface); 7] = [
+        (crate::MOVE_SLIMES_SHADER, layouts::MOVE_SLIMES),
+        (crate::LIFECYCLE_SHADER, layouts::LIFECYCLE),
+        (crate::FOOD_SHADER, layouts::FOOD_SOURCES),
+        (crate::UPDATE_WORLD_SHADER, layouts::UPDATE_WORLD),
+        (crate::WORLD_TO_TEX_SHADER, layouts::WORLD_TO_TEX),
+        (crate::RENDER_SHADER, layouts::RENDER),
+        (crate::DOWNSAMPLE_SHADER, layouts::DOWNSAMPLE_MIPS),
+    ];
+
+    fn parse(name: &str) -> Module {
+        ShaderDir::Embedded
+            .parse(name)
+            .unwrap_or_else(|e| panic!("{}", e))
+            .1
+    }
+
+    #[test]
+    fn every_shader_matches_its_layout() {
+        for shader in crate::SHADERS {
+            let (name, interface) = SHADER_INTERFACES
+                .iter()
+                .find(|(name, _)| *name == shader)
+                .unwrap_or_else(|| panic!("{} has no interface to check against", shader));
+            if let Err(e) = validate(&parse(name), interface) {
+                panic!("{} doesn't match its layout:\n{}", name, e);
+            }
+        }
+    }
+
+    #[test]
+    fn mismatched_layouts_are_rejected() {
+        let module = parse(crate::UPDATE_WORLD_SHADER);
+
+        // The move config is 12 bytes, the world update params 16.
+        const WRONG_UNIFORM: GroupLayout = GroupLayout {
+            name: "wrong uniform",
+            bindings: &[Binding {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                resource: Resource::Uniform(&layouts::SLIME_MOVE_CONFIG),
+            }],
+        };
+        let e = validate(
+            &module,
+            &[&WRONG_UNIFORM, &layouts::WORLD, &layouts::STATIC],
+        )
+        .unwrap_err();
+        assert!(e.contains("`params`"), "{}", e);
+
+        // Groups in the wrong order.
+        let e = validate(
+            &module,
+            &[&layouts::WORLD_UPDATE, &layouts::STATIC, &layouts::WORLD],
+        )
+        .unwrap_err();
+        assert!(e.contains("group 1 binding 1"), "{}", e);
+    }
+}
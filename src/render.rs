@@ -0,0 +1,118 @@
+//! Headless rendering of every frame to numbered images, for videos that have to line up with
+//! a timeline or an audio track frame for frame.
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use workerpool::thunk::{Thunk, ThunkWorker};
+use workerpool::Pool;
+
+use crate::food;
+use crate::params::SimParams;
+use crate::presets;
+use crate::shaders::{ShaderDir, ShaderLoader};
+use crate::simulation::{self, Simulation};
+use crate::sizing::SimSize;
+use crate::timeline::Animation;
+
+/// Frame rate without a timeline or audio, the same as a 60 fps window.
+const DEFAULT_FPS: f64 = 60.0;
+
+pub struct RenderOptions {
+    /// Frames to render, by default until the timeline or audio ends.
+    pub frames: Option<u64>,
+    /// Seed of the swarm, random if missing.
+    pub seed: Option<u64>,
+    /// Preset loaded before the animation starts.
+    pub preset: Option<String>,
+    /// Shown in the ffmpeg command printed at the end.
+    pub audio_path: Option<PathBuf>,
+}
+
+/// Renders to `videos/render-<time>/image-<frame>.png`, one image per simulation frame.
+pub fn run(
+    size: SimSize,
+    shader_dir: ShaderDir,
+    food_path: Option<&Path>,
+    animation: &Animation,
+    options: &RenderOptions,
+) -> Result<(), Box<dyn Error>> {
+    let frames = options
+        .frames
+        .or_else(|| animation.length())
+        .ok_or("nothing to render, give --frames, --timeline or --audio")?;
+    let fps = animation.fps().unwrap_or(DEFAULT_FPS);
+    let delta_time = animation.delta_time().unwrap_or((1.0 / DEFAULT_FPS) as f32);
+    let seed = options.seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64
+    });
+    let mut params = SimParams::default();
+    if let Some(name) = &options.preset {
+        presets::load(name)
+            .map_err(|e| format!("error loading preset {}: {}", name, e))?
+            .apply(&mut params);
+    }
+    let food_sources = match food_path {
+        Some(path) => food::load(path, size.world, params.food.radius)?,
+        None => Vec::new(),
+    };
+
+    let (device, queue) = pollster::block_on(simulation::headless_device(&size))?;
+    let shader_loader = ShaderLoader::new(shader_dir, &device);
+    let mut sim = Simulation::new(&device, &shader_loader, size, &params, food_sources, seed);
+
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis();
+    let dir = format!("videos/render-{}", time);
+    std::fs::create_dir_all(&dir)?;
+    println!("Rendering {} frames to {}, seed {}", frames, dir, seed);
+
+    let save_img_pool = Pool::<ThunkWorker<()>>::new(16);
+    let (width, height) = size.world;
+    let progress_interval = (1.0 / delta_time).round().max(1.0) as u64 * 10;
+    for frame in 0..frames {
+        if animation.apply(frame, &mut params) {
+            sim.write_params(&queue, &params);
+        }
+        sim.step(&device, &queue, delta_time, &params);
+        let data = sim.capture(&device, &queue);
+        let filepath = format!("{}/image-{}.png", dir, frame);
+        save_img_pool.execute(Thunk::of(move || {
+            image::save_buffer_with_format(
+                &filepath,
+                &data,
+                width,
+                height,
+                image::ColorType::Rgba8,
+                image::ImageFormat::Png,
+            )
+            .unwrap();
+        }));
+        if (frame + 1) % progress_interval == 0 {
+            println!("{} / {}", frame + 1, frames);
+        }
+    }
+    save_img_pool.join();
+
+    // The exact configured rate, a rounded one would drift against the audio track
+    match &options.audio_path {
+        Some(audio) => println!(
+            "Done. ffmpeg -framerate {} -i {}/image-%d.png -i {} -c:v libx264 -pix_fmt yuv420p -shortest render-{}.mp4",
+            fps,
+            dir,
+            audio.display(),
+            time
+        ),
+        None => println!(
+            "Done. ffmpeg -framerate {} -i {}/image-%d.png -c:v libx264 -pix_fmt yuv420p render-{}.mp4",
+            fps, dir, time
+        ),
+    }
+    Ok(())
+}
@@ -0,0 +1,453 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+
+use notify::DebouncedEvent;
+
+use crate::layouts::Interface;
+use crate::params::NUM_SPECIES;
+use crate::reflect;
+
+/// A shader that failed to load, with enough context to show it on screen.
+#[derive(Debug, Clone)]
+pub struct ShaderError {
+    pub path: String,
+    /// 1-based line and column, when the error points at a location in the source.
+    pub location: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.location {
+            Some((line, col)) => writeln!(f, "{}:{}:{}", self.path, line, col)?,
+            None => writeln!(f, "{}", self.path)?,
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Routes wgpu validation errors raised while creating pipelines back to the caller
+/// instead of the default handler, which panics.
+#[derive(Clone)]
+struct ErrorCapture {
+    capturing: Arc<AtomicBool>,
+    errors: Arc<Mutex<Vec<String>>>,
+}
+
+impl ErrorCapture {
+    fn install(device: &wgpu::Device) -> Self {
+        let capture = ErrorCapture {
+            capturing: Arc::new(AtomicBool::new(false)),
+            errors: Arc::new(Mutex::new(Vec::new())),
+        };
+        let handler = capture.clone();
+        device.on_uncaptured_error(move |e| {
+            if handler.capturing.load(Ordering::SeqCst) {
+                handler.errors.lock().unwrap().push(e.to_string());
+            } else {
+                panic!("wgpu error: {}", e);
+            }
+        });
+        capture
+    }
+
+    /// Runs `f`, collecting any wgpu errors it raises.
+    fn capture<R>(&self, f: impl FnOnce() -> R) -> (R, Vec<String>) {
+        self.capturing.store(true, Ordering::SeqCst);
+        let result = f();
+        self.capturing.store(false, Ordering::SeqCst);
+        let errors = std::mem::take(&mut *self.errors.lock().unwrap());
+        (result, errors)
+    }
+}
+
+/// Latest error per pipeline shader, cleared when that shader reloads successfully. The error
+/// itself may point into an included file.
+#[derive(Default)]
+pub struct ShaderErrors {
+    errors: Vec<(String, ShaderError)>,
+}
+
+impl ShaderErrors {
+    pub fn set(&mut self, shader: &str, error: ShaderError) {
+        self.clear(shader);
+        self.errors.push((shader.to_owned(), error));
+    }
+
+    pub fn clear(&mut self, shader: &str) {
+        self.errors.retain(|(s, _)| s != shader);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &ShaderError)> {
+        self.errors.iter().map(|(s, e)| (s.as_str(), e))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Constants injected ahead of every shader so WGSL and Rust agree on them.
+fn generated_constants() -> String {
+    format!("let NUM_SPECIES: u32 = {}u;\n", NUM_SPECIES)
+}
+
+/// Shaders compiled into the binary, keyed by their path relative to `src/shaders`.
+const EMBEDDED_SHADERS: &[(&str, &str)] = &[
+    ("common.wgsl", include_str!("shaders/common.wgsl")),
+    ("slimes.wgsl", include_str!("shaders/slimes.wgsl")),
+    ("move_slimes.wgsl", include_str!("shaders/move_slimes.wgsl")),
+    ("lifecycle.wgsl", include_str!("shaders/lifecycle.wgsl")),
+    ("food.wgsl", include_str!("shaders/food.wgsl")),
+    (
+        "update_world.wgsl",
+        include_str!("shaders/update_world.wgsl"),
+    ),
+    (
+        "world_to_tex.wgsl",
+        include_str!("shaders/world_to_tex.wgsl"),
+    ),
+    ("shader.wgsl", include_str!("shaders/shader.wgsl")),
+    ("downsample.wgsl", include_str!("shaders/downsample.wgsl")),
+];
+
+/// Where shader sources are read from.
+pub enum ShaderDir {
+    /// The shaders from `src/shaders`, embedded at compile time.
+    Embedded,
+    /// A directory on disk, used for development and shader packs. Files it doesn't contain
+    /// fall back to the embedded copy, so a pack only needs the shaders it changes.
+    Disk(PathBuf),
+}
+
+impl ShaderDir {
+    fn disk_path(&self, name: &str) -> Option<PathBuf> {
+        match self {
+            ShaderDir::Disk(dir) => Some(dir.join(name)).filter(|p| p.exists()),
+            ShaderDir::Embedded => None,
+        }
+    }
+
+    fn read(&self, name: &str) -> Result<String, String> {
+        if let Some(path) = self.disk_path(name) {
+            return std::fs::read_to_string(path).map_err(|e| e.to_string());
+        }
+        EMBEDDED_SHADERS
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, source)| source.to_string())
+            .ok_or_else(|| format!("no such shader: {}", name))
+    }
+
+    /// Name used in error messages, the full path when the shader comes from disk.
+    fn display(&self, name: &str) -> String {
+        match self.disk_path(name) {
+            Some(path) => path.display().to_string(),
+            None => name.to_owned(),
+        }
+    }
+
+    /// The directory to watch for hot reloading, if any.
+    pub fn watch_path(&self) -> Option<&Path> {
+        match self {
+            ShaderDir::Disk(dir) => Some(dir),
+            ShaderDir::Embedded => None,
+        }
+    }
+}
+
+/// A shader with its includes expanded.
+struct Preprocessed {
+    code: String,
+    /// File and 1-based line each line of `code` came from.
+    origins: Vec<(String, usize)>,
+}
+
+fn parse_include(line: &str) -> Option<&str> {
+    let rest = line.trim().strip_prefix("#include")?;
+    rest.trim().strip_prefix('"')?.strip_suffix('"')
+}
+
+/// Path of an included file, relative to the file including it.
+fn resolve_include(including: &str, include: &str) -> String {
+    match including.rfind('/') {
+        Some(i) => format!("{}/{}", &including[..i], include),
+        None => include.to_owned(),
+    }
+}
+
+fn file_name(name: &str) -> &str {
+    name.rsplit('/').next().unwrap_or(name)
+}
+
+impl ShaderDir {
+    /// Expands `#include "file.wgsl"` lines. Each file is included at most once, which also
+    /// breaks include cycles.
+    fn expand(
+        &self,
+        name: &str,
+        out: &mut Preprocessed,
+        seen: &mut Vec<String>,
+    ) -> Result<(), ShaderError> {
+        let display = self.display(name);
+        let source = self.read(name).map_err(|message| ShaderError {
+            path: display.clone(),
+            location: None,
+            message,
+        })?;
+        seen.push(name.to_owned());
+        for (i, line) in source.lines().enumerate() {
+            if let Some(include) = parse_include(line) {
+                let include_name = resolve_include(name, include);
+                if !seen.contains(&include_name) {
+                    self.expand(&include_name, out, seen)
+                        .map_err(|e| ShaderError {
+                            path: display.clone(),
+                            location: Some((i + 1, 1)),
+                            message: format!("in #include \"{}\": {}", include, e),
+                        })?;
+                }
+            } else {
+                out.code.push_str(line);
+                out.code.push('\n');
+                out.origins.push((display.clone(), i + 1));
+            }
+        }
+        Ok(())
+    }
+
+    fn preprocess(&self, name: &str) -> Result<Preprocessed, ShaderError> {
+        let mut out = Preprocessed {
+            code: String::new(),
+            origins: Vec::new(),
+        };
+        for (i, line) in generated_constants().lines().enumerate() {
+            out.code.push_str(line);
+            out.code.push('\n');
+            out.origins.push(("<generated>".to_owned(), i + 1));
+        }
+        self.expand(name, &mut out, &mut Vec::new())?;
+        Ok(out)
+    }
+
+    /// File names of everything `name` includes, directly or transitively. Files that can't be
+    /// read are skipped, the dependency list is only used to decide what to hot reload.
+    fn dependencies(&self, name: &str) -> Vec<String> {
+        let mut seen: Vec<String> = Vec::new();
+        let mut pending = vec![name.to_owned()];
+        while let Some(file) = pending.pop() {
+            if seen.contains(&file) {
+                continue;
+            }
+            if let Ok(source) = self.read(&file) {
+                pending.extend(
+                    source
+                        .lines()
+                        .filter_map(parse_include)
+                        .map(|i| resolve_include(&file, i)),
+                );
+            }
+            seen.push(file);
+        }
+        seen.iter()
+            .skip(1)
+            .map(|n| file_name(n).to_owned())
+            .collect()
+    }
+
+    /// Reads and preprocesses a shader, then parses it so syntax errors can point at the
+    /// original file and line. Returns the expanded source along with the parsed module.
+    pub fn parse(&self, name: &str) -> Result<(String, naga::Module), ShaderError> {
+        let shader = self.preprocess(name)?;
+        let module = match naga::front::wgsl::parse_str(&shader.code) {
+            Ok(module) => module,
+            Err(ref e) => {
+                let (line, col) = e.location(&shader.code);
+                let (file, file_line) = shader
+                    .origins
+                    .get(line - 1)
+                    .cloned()
+                    .unwrap_or_else(|| (self.display(name), line));
+                let source_line = shader.code.lines().nth(line - 1).unwrap_or("");
+                return Err(ShaderError {
+                    path: file,
+                    location: Some((file_line, col)),
+                    message: format!(
+                        "error: {}\n{:>5} | {}\n      | {:>width$}",
+                        e,
+                        file_line,
+                        source_line,
+                        "^",
+                        width = col
+                    ),
+                });
+            }
+        };
+        Ok((shader.code, module))
+    }
+}
+
+/// Reads shaders and builds pipelines from them, catching every kind of failure as a
+/// `ShaderError`.
+pub struct ShaderLoader {
+    pub dir: ShaderDir,
+    capture: ErrorCapture,
+}
+
+impl ShaderLoader {
+    pub fn new(dir: ShaderDir, device: &wgpu::Device) -> Self {
+        ShaderLoader {
+            dir,
+            capture: ErrorCapture::install(device),
+        }
+    }
+
+    /// The shaders in `roots` that need reloading after the files in `changed` were saved.
+    pub fn affected<'a>(&self, changed: &[String], roots: &[&'a str]) -> Vec<&'a str> {
+        roots
+            .iter()
+            .copied()
+            .filter(|root| {
+                changed.iter().any(|c| c == file_name(root))
+                    || self
+                        .dir
+                        .dependencies(root)
+                        .iter()
+                        .any(|d| changed.contains(d))
+            })
+            .collect()
+    }
+
+    /// Parses a shader and checks its bindings against the layouts the pipeline is created with.
+    fn load_source(&self, name: &str, interface: Interface) -> Result<String, ShaderError> {
+        let (code, module) = self.dir.parse(name)?;
+        reflect::validate(&module, interface).map_err(|message| ShaderError {
+            path: self.dir.display(name),
+            location: None,
+            message: format!("bindings don't match the Rust layouts:\n{}", message),
+        })?;
+        Ok(code)
+    }
+
+    /// Creates a pipeline with `create`, turning any wgpu validation errors into a
+    /// `ShaderError`.
+    fn create_pipeline<P>(&self, name: &str, create: impl FnOnce() -> P) -> Result<P, ShaderError> {
+        let (pipeline, wgpu_errors) = self.capture.capture(create);
+        if wgpu_errors.is_empty() {
+            Ok(pipeline)
+        } else {
+            Err(ShaderError {
+                path: self.dir.display(name),
+                location: None,
+                message: wgpu_errors.join("\n"),
+            })
+        }
+    }
+
+    pub fn load_pipeline(
+        &self,
+        name: &str,
+        device: &wgpu::Device,
+        pipeline_layout: &wgpu::PipelineLayout,
+        interface: Interface,
+    ) -> Result<wgpu::ComputePipeline, ShaderError> {
+        let shader_code = self.load_source(name, interface)?;
+        self.create_pipeline(name, || {
+            let cs_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+                label: Some(name),
+                source: wgpu::ShaderSource::Wgsl(shader_code.into()),
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(name),
+                layout: Some(pipeline_layout),
+                module: &cs_module,
+                entry_point: "main",
+            })
+        })
+    }
+
+    /// Loads the fullscreen pipeline that copies the world texture to the swapchain.
+    pub fn load_render_pipeline(
+        &self,
+        name: &str,
+        device: &wgpu::Device,
+        pipeline_layout: &wgpu::PipelineLayout,
+        format: wgpu::TextureFormat,
+        interface: Interface,
+    ) -> Result<wgpu::RenderPipeline, ShaderError> {
+        let shader_code = self.load_source(name, interface)?;
+        self.create_pipeline(name, || {
+            let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+                label: Some(name),
+                source: wgpu::ShaderSource::Wgsl(shader_code.into()),
+            });
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(name),
+                layout: Some(pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &module,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &module,
+                    entry_point: "fs_main",
+                    targets: &[format.into()],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+            })
+        })
+    }
+}
+
+/// Replaces `pipeline` with the result of `load`. On failure the previous pipeline is kept and
+/// the error is recorded so it can be shown in the overlay.
+pub fn reload<P>(
+    pipeline: &mut P,
+    path: &str,
+    errors: &mut ShaderErrors,
+    load: impl FnOnce() -> Result<P, ShaderError>,
+) {
+    match load() {
+        Ok(p) => {
+            *pipeline = p;
+            errors.clear(path);
+            println!("Reloaded Shader: {}", path);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            errors.set(path, e);
+        }
+    }
+}
+
+/// Drains the watcher channel and returns the file names of shaders that were written or
+/// replaced. Editors often save by writing a temporary file and renaming it over the original,
+/// so creates and renames count as well. Removals are ignored, the current pipeline stays
+/// until the file comes back.
+pub fn changed_shaders(rx: &Receiver<DebouncedEvent>) -> Vec<String> {
+    let mut names = Vec::new();
+    for event in rx.try_iter() {
+        let path = match event {
+            DebouncedEvent::Write(p) | DebouncedEvent::Create(p) | DebouncedEvent::Rename(_, p) => {
+                p
+            }
+            DebouncedEvent::Error(e, _) => {
+                eprintln!("Shader watcher error: {}", e);
+                continue;
+            }
+            _ => continue,
+        };
+        if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+            if !names.iter().any(|n| n == name) {
+                names.push(name.to_owned());
+            }
+        }
+    }
+    names
+}
@@ -0,0 +1,52 @@
+// Shared prelude for the compute shaders, pulled in with `#include "common.wgsl"`.
+// Groups 1 and 2 are laid out the same way in every compute pipeline: the world buffers
+// and the static params. Group 0 is free for each shader's own params.
+
+[[block]]
+struct StaticParams {
+    width: u32;
+    height: u32;
+    num_slimes: u32;
+    // Width of the 2D slime dispatch, in slimes.
+    slimes_per_row: u32;
+};
+
+// A plane of `width * height` trail values per species, one after the other.
+[[block]]
+struct World {
+    values: [[stride(4)]] array<f32>;
+};
+
+[[group(1), binding(0)]] var<storage, read> input_buf: World;
+[[group(1), binding(1)]] var<storage, read_write> output_buf: World;
+[[group(2), binding(0)]] var<uniform> static_params: StaticParams;
+
+let pi: f32 = 3.14159265359;
+
+fn in_bounds(index: vec2<i32>) -> bool {
+    return index.x >= 0 && index.y >= 0 && index.x < i32(static_params.width) && index.y < i32(static_params.height);
+}
+
+// Position of a species' trail at `index` in the world buffers. `index` must be in bounds.
+fn world_index(index: vec2<i32>, species: u32) -> u32 {
+    let plane = static_params.width * static_params.height;
+    return u32(index.x) + u32(index.y) * static_params.width + species * plane;
+}
+
+fn load(index: vec2<i32>, species: u32) -> f32 {
+    if (in_bounds(index)) {
+        return input_buf.values[world_index(index, species)];
+    } else {
+        return 0.0;
+    }
+}
+
+fn store(index: vec2<i32>, species: u32, value: f32) -> void {
+    if (in_bounds(index)) {
+        output_buf.values[world_index(index, species)] = value;
+    }
+}
+
+fn rand(co: vec2<f32>) -> f32 {
+    return fract(sin(dot(co, vec2<f32>(12.9898, 78.233))) * 43758.5453);
+}
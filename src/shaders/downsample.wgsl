@@ -0,0 +1,29 @@
+// Averages one mip level of the world texture into the next, half its size. Each texel covers a
+// 2x2 block of the larger level, and the last row and column of an odd sized level are folded
+// into the block before them, so every texel counts.
+
+[[group(0), binding(0)]] var source: texture_2d<f32>;
+[[group(0), binding(1)]] var dest: texture_storage_2d<rgba8unorm, write>;
+
+[[stage(compute), workgroup_size(8, 8, 1)]]
+fn main([[builtin(global_invocation_id)]] global_ix: vec3<u32>) {
+    let index = vec2<i32>(global_ix.xy);
+    let size = textureDimensions(dest);
+    if (index.x >= size.x || index.y >= size.y) {
+        return;
+    }
+    let source_size = textureDimensions(source);
+    let start = index * 2;
+    var end : vec2<i32> = min(start + vec2<i32>(2, 2), source_size);
+    if (index.x == size.x - 1) { end.x = source_size.x; }
+    if (index.y == size.y - 1) { end.y = source_size.y; }
+
+    var sum : vec4<f32> = vec4<f32>(0.0, 0.0, 0.0, 0.0);
+    for (var y : i32 = start.y; y < end.y; y = y + 1) {
+        for (var x : i32 = start.x; x < end.x; x = x + 1) {
+            sum = sum + textureLoad(source, vec2<i32>(x, y), 0);
+        }
+    }
+    let count = f32((end.x - start.x) * (end.y - start.y));
+    textureStore(dest, index, sum / count);
+}
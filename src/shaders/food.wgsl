@@ -0,0 +1,61 @@
+#include "common.wgsl"
+
+[[block]]
+struct Params {
+    emission: f32;
+    consumption: f32;
+    radius: i32;
+    num_sources: u32;
+};
+
+struct FoodSource {
+    pos: vec2<f32>;
+    nutrients: f32;
+    capacity: f32;
+};
+
+[[block]]
+struct Foods {
+    sources: [[stride(16)]] array<FoodSource>;
+};
+
+[[group(0), binding(0)]] var<uniform> params: Params;
+[[group(0), binding(1)]] var<storage, read_write> foods: Foods;
+
+// Runs right after `move_slimes.wgsl` with the same world bindings, so `output_buf` is the
+// input plus whatever the slimes deposited this frame. That difference is what gets eaten.
+// Overlapping sources race on the trail between them, see `food::merge_overlapping`.
+[[stage(compute), workgroup_size(64, 1, 1)]]
+fn main([[builtin(global_invocation_id)]] global_ix: vec3<u32>) {
+    if (global_ix.x >= params.num_sources) {
+        return;
+    }
+    let source = foods.sources[global_ix.x];
+    if (source.nutrients <= 0.0) {
+        return;
+    }
+
+    let center = vec2<i32>(source.pos);
+    let emit = params.emission * source.nutrients / source.capacity;
+    var eaten : f32 = 0.0;
+    var x : i32 = -params.radius;
+    loop {
+        if (x > params.radius) { break; }
+        var y : i32 = -params.radius;
+        loop {
+            if (y > params.radius) { break; }
+            let point = center + vec2<i32>(x, y);
+            if (x * x + y * y <= params.radius * params.radius && in_bounds(point)) {
+                for (var species : u32 = 0u; species < NUM_SPECIES; species = species + 1u) {
+                    let i = world_index(point, species);
+                    eaten = eaten + max(output_buf.values[i] - input_buf.values[i], 0.0);
+                    output_buf.values[i] = output_buf.values[i] + emit;
+                }
+            }
+            y = y + 1;
+        }
+        x = x + 1;
+    }
+
+    foods.sources[global_ix.x].nutrients = max(source.nutrients - eaten * params.consumption, 0.0);
+}
@@ -0,0 +1,47 @@
+#include "common.wgsl"
+#include "slimes.wgsl"
+
+// Scales a gene by up to `genetics.mutation` either way.
+fn mutate(gene: f32, seed: f32) -> f32 {
+    let r = rand(vec2<f32>(seed, params.random)) * 2.0 - 1.0;
+    return max(gene * (1.0 + r * genetics.mutation), 0.0);
+}
+
+// Runs after `move_slimes.wgsl` has filled the free list. Slimes with enough energy split,
+// the new half takes the slot of a dead slime.
+[[stage(compute), workgroup_size(64, 1, 1)]]
+fn main([[builtin(global_invocation_id)]] global_ix: vec3<u32>) {
+    let index = slime_index(global_ix);
+    if (lifecycle.enabled == 0u || index >= static_params.num_slimes) {
+        return;
+    }
+
+    let parent = slimes.slimes[index];
+    if (parent.energy <= lifecycle.split_energy) {
+        return;
+    }
+
+    // Only pops happen in this pass, so a failed pop can put its slot count straight back.
+    let free = atomicSub(&free_list.count, 1);
+    if (free <= 0) {
+        let restored = atomicAdd(&free_list.count, 1);
+        return;
+    }
+
+    // Both halves end up with at most 0.5 energy, so neither splits again while another
+    // invocation may still be reading it. `SimParams::clamp` keeps `split_energy` at 0.5 or above.
+    let energy = parent.energy * 0.5;
+    slimes.slimes[index].energy = energy;
+    var child: Slime = parent;
+    child.energy = energy;
+    child.heading = parent.heading + pi;
+    if (genetics.enabled != 0u) {
+        let seed = f32(index) * 4.0;
+        child.genes.move_speed = mutate(parent.genes.move_speed, seed);
+        child.genes.turn_speed = mutate(parent.genes.turn_speed, seed + 1.0);
+        child.genes.sensor_distance = mutate(parent.genes.sensor_distance, seed + 2.0);
+        child.genes.sensor_angle = mutate(parent.genes.sensor_angle, seed + 3.0);
+    }
+    let child_index = free_list.slots[free - 1];
+    slimes.slimes[child_index] = child;
+}
@@ -16,12 +16,81 @@ fn vs_main(
     return out;
 }
 
+// How the world texture is scaled to the window, see `present.rs`.
+[[block]]
+struct Present {
+    filter: u32;
+    pad0: u32;
+    pad1: u32;
+    pad2: u32;
+};
+
 [[group(0), binding(0)]]
 var r_color: texture_2d<f32>;
 [[group(0), binding(1)]]
 var r_sampler: sampler;
+// Linear within and between mip levels.
+[[group(0), binding(2)]]
+var r_linear: sampler;
+[[group(0), binding(3)]]
+var<uniform> present: Present;
+
+let FILTER_NEAREST: u32 = 0u;
+let FILTER_LINEAR: u32 = 1u;
+let FILTER_AREA: u32 = 2u;
+
+// Catmull-Rom weights of the four texels around a point `t` of the way between the middle two.
+fn catmull_rom(t: f32) -> vec4<f32> {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    return vec4<f32>(
+        -0.5 * t3 + t2 - 0.5 * t,
+        1.5 * t3 - 2.5 * t2 + 1.0,
+        -1.5 * t3 + 2.0 * t2 + 0.5 * t,
+        0.5 * t3 - 0.5 * t2
+    );
+}
+
+fn cubic_row(base: vec2<i32>, y: i32, level: i32, last: vec2<i32>, w: vec4<f32>) -> vec4<f32> {
+    let row = clamp(base.y + y, 0, last.y);
+    return textureLoad(r_color, vec2<i32>(clamp(base.x - 1, 0, last.x), row), level) * w.x
+        + textureLoad(r_color, vec2<i32>(clamp(base.x, 0, last.x), row), level) * w.y
+        + textureLoad(r_color, vec2<i32>(clamp(base.x + 1, 0, last.x), row), level) * w.z
+        + textureLoad(r_color, vec2<i32>(clamp(base.x + 2, 0, last.x), row), level) * w.w;
+}
+
+// Bicubic from the mip level that is at most twice the window's resolution, so shrinking a
+// large world doesn't skip texels.
+fn bicubic(tex_coord: vec2<f32>) -> vec4<f32> {
+    let texels = tex_coord * vec2<f32>(textureDimensions(r_color));
+    let footprint = max(length(dpdx(texels)), length(dpdy(texels)));
+    let level = clamp(i32(floor(log2(max(footprint, 1.0)))), 0, textureNumLevels(r_color) - 1);
+
+    let size = textureDimensions(r_color, level);
+    let texel = tex_coord * vec2<f32>(size) - vec2<f32>(0.5, 0.5);
+    let base = vec2<i32>(floor(texel));
+    let fraction = texel - floor(texel);
+    let wx = catmull_rom(fraction.x);
+    let wy = catmull_rom(fraction.y);
+    let last = size - vec2<i32>(1, 1);
+    let color = cubic_row(base, -1, level, last, wx) * wy.x
+        + cubic_row(base, 0, level, last, wx) * wy.y
+        + cubic_row(base, 1, level, last, wx) * wy.z
+        + cubic_row(base, 2, level, last, wx) * wy.w;
+    // Catmull-Rom overshoots next to sharp edges
+    return clamp(color, vec4<f32>(0.0, 0.0, 0.0, 0.0), vec4<f32>(1.0, 1.0, 1.0, 1.0));
+}
 
 [[stage(fragment)]]
 fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
-    return textureSample(r_color, r_sampler, in.tex_coord);
+    if (present.filter == FILTER_NEAREST) {
+        return textureSampleLevel(r_color, r_sampler, in.tex_coord, 0.0);
+    }
+    if (present.filter == FILTER_LINEAR) {
+        return textureSampleLevel(r_color, r_linear, in.tex_coord, 0.0);
+    }
+    if (present.filter == FILTER_AREA) {
+        return textureSample(r_color, r_linear, in.tex_coord);
+    }
+    return bicubic(in.tex_coord);
 }
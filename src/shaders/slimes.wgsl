@@ -0,0 +1,71 @@
+// The slime buffer and lifecycle state, shared by `move_slimes.wgsl` and `lifecycle.wgsl`.
+// Both run with the same group 0 layout.
+
+[[block]]
+struct Params {
+    delta_time: f32;
+    random: f32;
+    move_to_center: u32;
+};
+
+// A slime's own movement parameters, used instead of its species' when genetics is enabled.
+struct Genes {
+    move_speed: f32;
+    turn_speed: f32;
+    sensor_distance: f32;
+    sensor_angle: f32;
+};
+
+struct Slime {
+    pos: vec2<f32>;
+    heading: f32;
+    species: u32;
+    // Slimes with no energy left are dead. Their slots are reused for new slimes.
+    energy: f32;
+    genes: Genes;
+    pad0: f32;
+};
+
+[[block]]
+struct Slimes {
+    slimes: [[stride(40)]] array<Slime>;
+};
+
+// Indices of dead slimes. Rebuilt every frame: `count` is reset to 0 before the move pass,
+// dead slimes push themselves there and the lifecycle pass pops slots for new slimes.
+[[block]]
+struct FreeList {
+    count: atomic<i32>;
+    slots: [[stride(4)]] array<u32>;
+};
+
+[[block]]
+struct LifecycleParams {
+    enabled: u32;
+    // Energy lost each frame.
+    drain: f32;
+    // Energy gained per unit of the slime's own trail under it.
+    gain: f32;
+    // Slimes with more energy than this split in two. Energy is capped at 1.
+    split_energy: f32;
+};
+
+[[block]]
+struct GeneticsParams {
+    enabled: u32;
+    // Largest relative change of each gene when it is passed on.
+    mutation: f32;
+    pad0: f32;
+    pad1: f32;
+};
+
+[[group(0), binding(0)]] var<uniform> params: Params;
+[[group(0), binding(1)]] var<storage, read_write> slimes: Slimes;
+[[group(0), binding(3)]] var<storage, read_write> free_list: FreeList;
+[[group(0), binding(4)]] var<uniform> lifecycle: LifecycleParams;
+[[group(0), binding(6)]] var<uniform> genetics: GeneticsParams;
+
+fn slime_index(global_ix: vec3<u32>) -> u32 {
+    // The dispatch wraps into rows when there are too many slimes for one dimension.
+    return global_ix.x + global_ix.y * static_params.slimes_per_row;
+}
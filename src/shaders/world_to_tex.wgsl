@@ -1,34 +1,21 @@
-[[block]] struct World {
-    values: array<vec4<f32>>;
-};
+#include "common.wgsl"
 
 [[block]]
-struct StaticParams {
-    width: u32;
-    height: u32;
-    num_slimes: u32;
+struct Palette {
+    tints: array<vec4<f32>, NUM_SPECIES>;
+    brightness: f32;
 };
 
-
+// Where this world sits in the whole picture, in pixels. Only tiled renders draw part of one.
 [[block]]
-struct Params {
-    delta_time: f32;
-    random: f32;
+struct View {
+    origin: vec2<f32>;
+    size: vec2<f32>;
 };
 
-[[group(1), binding(0)]] var<uniform> static_params: StaticParams;
-[[group(2), binding(0)]] var<uniform> params: Params;
-[[group(0), binding(0)]] var<storage, read> input_buf: World;
-[[group(0), binding(1)]] var output_tex: texture_storage_2d<rgba8unorm, write>;
-
-fn load(index: vec2<i32>) -> vec4<f32> {
-    return input_buf.values[index.x + index.y * i32(static_params.width)];
-}
-
-fn rand(seed: f32) -> f32 {
-    let co = vec2<f32>(seed, params.random);
-    return fract(sin(dot(co, vec2<f32>(12.9898, 78.233))) * 43758.5453);
-}
+[[group(0), binding(0)]] var output_tex: texture_storage_2d<rgba8unorm, write>;
+[[group(0), binding(1)]] var<uniform> palette: Palette;
+[[group(0), binding(2)]] var<uniform> view: View;
 
 // fn random(seed: f32) -> f32 {
 //     let p = vec2<f32>(seed, params.random);
@@ -81,20 +68,41 @@ fn map01(v: f32, a: f32, b: f32) -> f32 {
     return v * (b - a) + a;
 }
 
+// Colour gradient across the world for each of the first four species. Further species are
+// coloured by their palette tint alone.
+fn gradient(species: u32, frag: vec2<f32>) -> vec3<f32> {
+    // let c1 = vec4<f32>(map01(1.0 - frag.x, -0.2, 0.4), 1.0, map01(1.0 - frag.y, -0.4, 0.02), 1.0) * val.x;
+    // let c2 = vec4<f32>(map01(frag.y, 1.0, 0.0), frag.x * 0.1, map01(frag.y, 0.0, 1.0), 1.0) * val.y;
+    if (species == 0u) {
+        return vec3<f32>(0.0, frag.x * 0.5 + 0.05, frag.y * 3.0 + 0.3);
+    }
+    if (species == 1u) {
+        return vec3<f32>(map(cos(frag.x * 6.28), -1.0, 1.0, 0.4, 0.6), frag.x * 0.1 + 0.2, frag.y * 1.0 + 1.0);
+    }
+    if (species == 2u) {
+        return vec3<f32>(0.0, frag.x * 1.0 + 0.3, (1.0 - frag.y) * 1.0 + 0.1);
+    }
+    if (species == 3u) {
+        return vec3<f32>(map01(frag.x * frag.y, 0.0, 0.5), frag.x * 1.5 + 0.05, frag.y * 2.0 + 0.1);
+    }
+    return vec3<f32>(1.0, 1.0, 1.0);
+}
+
 [[stage(compute), workgroup_size(8, 8, 1)]]
 fn main([[builtin(global_invocation_id)]] global_ix: vec3<u32>) {
     let tex_index = vec2<i32>(global_ix.xy);
-    let val = load(tex_index);
+    if (!in_bounds(tex_index)) {
+        return;
+    }
     // let val = rand(f32(global_ix.x + global_ix.y * static_params.width) / f32(static_params.width * static_params.height));
-    let frag = vec2<f32>(global_ix.xy) / vec2<f32>(f32(static_params.width), f32(static_params.height));
-    // let c1 = vec4<f32>(map01(1.0 - frag.x, -0.2, 0.4), 1.0, map01(1.0 - frag.y, -0.4, 0.02), 1.0) * val.x;
-    // let c2 = vec4<f32>(map01(frag.y, 1.0, 0.0), frag.x * 0.1, map01(frag.y, 0.0, 1.0), 1.0) * val.y;
-    let c1 = vec3<f32>(0.0, frag.x * 0.5 + 0.05, frag.y * 3.0 + 0.3) * val.r;
-    let c2 = vec3<f32>(map(cos(frag.x * 6.28), -1.0, 1.0, 0.4, 0.6), frag.x * 0.1 + 0.2, frag.y * 1.0 + 1.0) * val.g;
-    let c3 = vec3<f32>(0.0, frag.x * 1.0 + 0.3, (1.0 - frag.y) * 1.0 + 0.1) * val.b;
-    let c4 = vec3<f32>(map01(frag.x * frag.y, 0.0, 0.5), frag.x * 1.5 + 0.05, frag.y * 2.0 + 0.1) * val.a;
+    let frag = (view.origin + vec2<f32>(global_ix.xy)) / view.size;
     // let color = vec3<f32>(frag * sample_filter(vec2<f32>(tex_index)), 0.3);
-    let color = (c1 + c2 + c3 + c4) * 0.8;
+    var color : vec3<f32> = vec3<f32>(0.0, 0.0, 0.0);
+    for (var species : u32 = 0u; species < NUM_SPECIES; species = species + 1u) {
+        let t = palette.tints[species];
+        color = color + gradient(species, frag) * load(tex_index, species) * t.rgb * t.a;
+    }
+    color = color * palette.brightness;
     // let color = vec4<f32>(val, val, val, 1.0);
     textureStore(output_tex, tex_index, vec4<f32>(color.rgb, 1.0));
-}
\ No newline at end of file
+}
@@ -0,0 +1,827 @@
+//! The GPU side of the simulation: world and slime buffers, the compute pipelines and the world
+//! texture they draw into. Used by the window and by the headless commands.
+
+use std::num::NonZeroU32;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use wgpu::util::DeviceExt;
+use wgpu::{BufferUsages, Extent3d, Features, TextureUsages};
+
+use crate::food::FoodSource;
+use crate::params::{SimParams, NUM_SPECIES};
+use crate::shaders::{reload, ShaderErrors, ShaderLoader};
+use crate::sizing::{self, padded_bytes_per_row, SimSize, SizeError};
+use crate::{
+    genes, layouts, swarm, FoodConfig, Slime, SlimeMoveConfig, StaticGlobalParams,
+    WorldUpdateConfig, WorldView, FOOD_SHADER, LIFECYCLE_SHADER, MOVE_SLIMES_SHADER,
+    TEXTURE_FORMAT, UPDATE_WORLD_SHADER, WORLD_TO_TEX_SHADER,
+};
+
+/// Requests a device with the limits `size` needs, or the size problems if the adapter can't
+/// run it.
+pub async fn request_device(
+    adapter: &wgpu::Adapter,
+    size: &SimSize,
+) -> Result<(wgpu::Device, wgpu::Queue), SizeError> {
+    let limits = size.required_limits(&adapter.get_info().name, &adapter.limits())?;
+    Ok(adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                features: adapter.features() | Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+                limits,
+            },
+            None,
+        )
+        .await
+        .expect("error creating device"))
+}
+
+/// A device without a window, for the headless commands.
+pub async fn headless_device(
+    size: &SimSize,
+) -> Result<(wgpu::Device, wgpu::Queue), Box<dyn std::error::Error>> {
+    let instance = wgpu::Instance::new(wgpu::Backends::PRIMARY);
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            force_fallback_adapter: false,
+            compatible_surface: None,
+        })
+        .await
+        .ok_or("no GPU adapter found")?;
+    Ok(request_device(&adapter, size).await?)
+}
+
+pub struct Simulation {
+    pub size: SimSize,
+    rng: StdRng,
+    /// The swarm `reset` starts over from.
+    slimes: Vec<Slime>,
+    food_sources: Vec<FoodSource>,
+    /// `food_sources`, or one empty source since storage buffers can't be empty.
+    initial_food: Vec<FoodSource>,
+
+    pub world_texture: wgpu::Texture,
+    pub world_texture_view: wgpu::TextureView,
+    /// Buffer to copy the world texture into for saving it.
+    world_texture_copy_buffer: wgpu::Buffer,
+    pub current_world_buffer: wgpu::Buffer,
+    next_world_buffer: wgpu::Buffer,
+    world_bind_group: wgpu::BindGroup,
+    inverted_world_bind_group: wgpu::BindGroup,
+    static_params_buffer: wgpu::Buffer,
+    static_params_bind_group: wgpu::BindGroup,
+
+    slime_move_params_buffer: wgpu::Buffer,
+    pub slimes_buffer: wgpu::Buffer,
+    species_params_buffer: wgpu::Buffer,
+    interactions_buffer: wgpu::Buffer,
+    lifecycle_params_buffer: wgpu::Buffer,
+    genetics_params_buffer: wgpu::Buffer,
+    free_list_buffer: wgpu::Buffer,
+    slime_move_bind_group_layout: wgpu::BindGroupLayout,
+    slime_move_bind_group: wgpu::BindGroup,
+    slime_move_compute_pipeline_layout: wgpu::PipelineLayout,
+    slime_move_pipeline: wgpu::ComputePipeline,
+    lifecycle_pipeline: wgpu::ComputePipeline,
+
+    foods_buffer: wgpu::Buffer,
+    food_config_buffer: wgpu::Buffer,
+    food_bind_group: wgpu::BindGroup,
+    food_compute_pipeline_layout: wgpu::PipelineLayout,
+    food_pipeline: wgpu::ComputePipeline,
+
+    world_update_params_buffer: wgpu::Buffer,
+    world_update_bind_group: wgpu::BindGroup,
+    world_update_compute_pipeline_layout: wgpu::PipelineLayout,
+    world_update_pipeline: wgpu::ComputePipeline,
+
+    palette_buffer: wgpu::Buffer,
+    view_buffer: wgpu::Buffer,
+    buf_to_tex_bind_group: wgpu::BindGroup,
+    buf_to_tex_compute_pipeline_layout: wgpu::PipelineLayout,
+    buf_to_tex_pipeline: wgpu::ComputePipeline,
+}
+
+impl Simulation {
+    /// Sets up a world of `size` with a fresh swarm drawn from `seed`. Panics if a shader fails
+    /// to load.
+    pub fn new(
+        device: &wgpu::Device,
+        shader_loader: &ShaderLoader,
+        size: SimSize,
+        params: &SimParams,
+        food_sources: Vec<FoodSource>,
+        seed: u64,
+    ) -> Self {
+        let load = |name, layout, interface| {
+            shader_loader
+                .load_pipeline(name, device, layout, interface)
+                .unwrap_or_else(|e| panic!("{}", e))
+        };
+
+        let world_texture_copy_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: size.readback_buffer_size(),
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        // Create two buffers for the world data, and a texture to render, (custom swapchain)
+        let world_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: Extent3d {
+                width: size.world.0,
+                height: size.world.1,
+                depth_or_array_layers: 1,
+            },
+            // Filled in by `present::MipChain` when the window needs the smaller levels
+            mip_level_count: size.mip_levels(),
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: TEXTURE_FORMAT,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::COPY_SRC
+                | TextureUsages::STORAGE_BINDING,
+        });
+        let world_texture_view = world_texture.create_view(&Default::default());
+        // Storage bindings can only see one level
+        let world_storage_view = world_texture.create_view(&wgpu::TextureViewDescriptor {
+            mip_level_count: NonZeroU32::new(1),
+            ..Default::default()
+        });
+
+        let init_world_data =
+            vec![0.0f32; size.world.0 as usize * size.world.1 as usize * NUM_SPECIES];
+        let current_world_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&init_world_data),
+            usage: BufferUsages::COPY_SRC | BufferUsages::COPY_DST | BufferUsages::STORAGE,
+        });
+        let next_world_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&init_world_data),
+            usage: BufferUsages::COPY_DST | BufferUsages::STORAGE,
+        });
+
+        // === World Map Swap Chain === //
+        let world_bind_group_layout = layouts::WORLD.create(device);
+        let world_bind_group = create_world_bind_group(
+            device,
+            &world_bind_group_layout,
+            &current_world_buffer,
+            &next_world_buffer,
+        );
+        let inverted_world_bind_group = create_world_bind_group(
+            device,
+            &world_bind_group_layout,
+            &next_world_buffer,
+            &current_world_buffer,
+        );
+
+        // ========== Static Global Params Bind Group ====== //
+        let static_params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+            contents: bytemuck::cast_slice(&[StaticGlobalParams::new(&size)]),
+        });
+        let static_params_bind_group_layout = layouts::STATIC.create(device);
+        let static_params_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &static_params_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: static_params_buffer.as_entire_binding(),
+            }],
+        });
+        let pipeline_layout = |group_layout| {
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[
+                    group_layout,
+                    &world_bind_group_layout,
+                    &static_params_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            })
+        };
+
+        // ========== Slime Movement Shader ========== //
+        let mut rng = StdRng::seed_from_u64(seed);
+        let slimes = Slime::new_swarm(
+            size.num_slimes as usize,
+            size.world,
+            &params.swarm.species_weights,
+            &params.species,
+            &mut rng,
+        );
+        let slimes_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            usage: swarm::SLIMES_BUFFER_USAGES,
+            contents: bytemuck::cast_slice(&slimes),
+        });
+        let slime_move_params_buffer =
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                usage: BufferUsages::COPY_DST | BufferUsages::STORAGE | BufferUsages::UNIFORM,
+                contents: bytemuck::cast_slice(&[SlimeMoveConfig {
+                    delta_time: 0.0,
+                    random: 0.0,
+                    move_to_center: 0,
+                }]),
+            });
+        let species_params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+            contents: bytemuck::cast_slice(&[params.species]),
+        });
+        let interactions_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            usage: BufferUsages::COPY_DST | BufferUsages::STORAGE,
+            contents: bytemuck::cast_slice(&params.interactions.weights),
+        });
+        let lifecycle_params_buffer =
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+                contents: bytemuck::cast_slice(&[params.lifecycle]),
+            });
+        let genetics_params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+            contents: bytemuck::cast_slice(&[params.genetics]),
+        });
+        let free_list_buffer = create_free_list_buffer(device, &size);
+
+        let slime_move_bind_group_layout = layouts::SLIME_MOVE.create(device);
+        let slime_move_compute_pipeline_layout = pipeline_layout(&slime_move_bind_group_layout);
+        let slime_move_pipeline = load(
+            MOVE_SLIMES_SHADER,
+            &slime_move_compute_pipeline_layout,
+            layouts::MOVE_SLIMES,
+        );
+        let lifecycle_pipeline = load(
+            LIFECYCLE_SHADER,
+            &slime_move_compute_pipeline_layout,
+            layouts::LIFECYCLE,
+        );
+        let slime_move_bind_group = create_slime_move_bind_group(
+            device,
+            &slime_move_bind_group_layout,
+            [
+                &slime_move_params_buffer,
+                &slimes_buffer,
+                &species_params_buffer,
+                &free_list_buffer,
+                &lifecycle_params_buffer,
+                &interactions_buffer,
+                &genetics_params_buffer,
+            ],
+        );
+
+        // ========== Food Sources ============ //
+        let initial_food = if food_sources.is_empty() {
+            vec![bytemuck::Zeroable::zeroed()]
+        } else {
+            food_sources.clone()
+        };
+        let foods_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            usage: BufferUsages::COPY_DST | BufferUsages::STORAGE,
+            contents: bytemuck::cast_slice(&initial_food),
+        });
+        let food_config_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+            contents: bytemuck::cast_slice(&[FoodConfig::new(&params.food, food_sources.len())]),
+        });
+        let food_bind_group_layout = layouts::FOOD.create(device);
+        let food_compute_pipeline_layout = pipeline_layout(&food_bind_group_layout);
+        let food_pipeline = load(
+            FOOD_SHADER,
+            &food_compute_pipeline_layout,
+            layouts::FOOD_SOURCES,
+        );
+        let food_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &food_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: food_config_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: foods_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        // ========== World Processing Shader ============ //
+        let world_update_params_buffer =
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+                contents: bytemuck::cast_slice(&[WorldUpdateConfig::new(0.0, &params.world)]),
+            });
+        let world_update_bind_group_layout = layouts::WORLD_UPDATE.create(device);
+        let world_update_compute_pipeline_layout = pipeline_layout(&world_update_bind_group_layout);
+        let world_update_pipeline = load(
+            UPDATE_WORLD_SHADER,
+            &world_update_compute_pipeline_layout,
+            layouts::UPDATE_WORLD,
+        );
+        let world_update_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &world_update_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: world_update_params_buffer.as_entire_binding(),
+            }],
+        });
+
+        // ================== BUFFER TO TEXTURE COMPUTE SHADER ================== //
+        let palette_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+            contents: bytemuck::cast_slice(&[params.palette]),
+        });
+        let view_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+            contents: bytemuck::cast_slice(&[WorldView::new((0.0, 0.0), size.world)]),
+        });
+        let buf_to_tex_bind_group_layout = layouts::BUF_TO_TEX.create(device);
+        let buf_to_tex_compute_pipeline_layout = pipeline_layout(&buf_to_tex_bind_group_layout);
+        let buf_to_tex_pipeline = load(
+            WORLD_TO_TEX_SHADER,
+            &buf_to_tex_compute_pipeline_layout,
+            layouts::WORLD_TO_TEX,
+        );
+        let buf_to_tex_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &buf_to_tex_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&world_storage_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: palette_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: view_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Simulation {
+            size,
+            rng,
+            slimes,
+            food_sources,
+            initial_food,
+            world_texture,
+            world_texture_view,
+            world_texture_copy_buffer,
+            current_world_buffer,
+            next_world_buffer,
+            world_bind_group,
+            inverted_world_bind_group,
+            static_params_buffer,
+            static_params_bind_group,
+            slime_move_params_buffer,
+            slimes_buffer,
+            species_params_buffer,
+            interactions_buffer,
+            lifecycle_params_buffer,
+            genetics_params_buffer,
+            free_list_buffer,
+            slime_move_bind_group_layout,
+            slime_move_bind_group,
+            slime_move_compute_pipeline_layout,
+            slime_move_pipeline,
+            lifecycle_pipeline,
+            foods_buffer,
+            food_config_buffer,
+            food_bind_group,
+            food_compute_pipeline_layout,
+            food_pipeline,
+            world_update_params_buffer,
+            world_update_bind_group,
+            world_update_compute_pipeline_layout,
+            world_update_pipeline,
+            palette_buffer,
+            view_buffer,
+            buf_to_tex_bind_group,
+            buf_to_tex_compute_pipeline_layout,
+            buf_to_tex_pipeline,
+        }
+    }
+
+    /// Uploads every parameter that lives in a buffer of its own.
+    pub fn write_params(&self, queue: &wgpu::Queue, params: &SimParams) {
+        queue.write_buffer(
+            &self.species_params_buffer,
+            0,
+            bytemuck::cast_slice(&[params.species]),
+        );
+        queue.write_buffer(
+            &self.interactions_buffer,
+            0,
+            bytemuck::cast_slice(&params.interactions.weights),
+        );
+        queue.write_buffer(
+            &self.lifecycle_params_buffer,
+            0,
+            bytemuck::cast_slice(&[params.lifecycle]),
+        );
+        queue.write_buffer(
+            &self.genetics_params_buffer,
+            0,
+            bytemuck::cast_slice(&[params.genetics]),
+        );
+        queue.write_buffer(
+            &self.palette_buffer,
+            0,
+            bytemuck::cast_slice(&[params.palette]),
+        );
+    }
+
+    /// Writes the per-frame uniforms and records one simulation step into `encoder`, ending
+    /// with the world drawn into `world_texture`.
+    pub fn encode_frame(
+        &mut self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        delta_time: f32,
+        params: &SimParams,
+        move_to_center: u32,
+    ) {
+        // ----- Update Uniforms ----- //
+        queue.write_buffer(
+            &self.slime_move_params_buffer,
+            0,
+            bytemuck::cast_slice(&[SlimeMoveConfig {
+                delta_time,
+                random: self.rng.gen_range(0.0..1.0),
+                move_to_center,
+            }]),
+        );
+        queue.write_buffer(
+            &self.world_update_params_buffer,
+            0,
+            bytemuck::cast_slice(&[WorldUpdateConfig::new(delta_time, &params.world)]),
+        );
+        queue.write_buffer(
+            &self.food_config_buffer,
+            0,
+            bytemuck::cast_slice(&[FoodConfig::new(&params.food, self.food_sources.len())]),
+        );
+        // The move pass rebuilds the free list from scratch every frame
+        queue.write_buffer(&self.free_list_buffer, 0, bytemuck::cast_slice(&[0i32]));
+
+        // ----- Run Compute Pipelines ----- //
+        {
+            let mut cpass = encoder.begin_compute_pass(&Default::default());
+            cpass.set_pipeline(&self.slime_move_pipeline);
+            cpass.set_bind_group(0, &self.slime_move_bind_group, &[]);
+            cpass.set_bind_group(1, &self.world_bind_group, &[]);
+            cpass.set_bind_group(2, &self.static_params_bind_group, &[]);
+            let (x, y) = self.size.slime_workgroups();
+            cpass.dispatch(x, y, 1);
+        }
+        if params.lifecycle.enabled != 0 {
+            let mut cpass = encoder.begin_compute_pass(&Default::default());
+            cpass.set_pipeline(&self.lifecycle_pipeline);
+            cpass.set_bind_group(0, &self.slime_move_bind_group, &[]);
+            cpass.set_bind_group(1, &self.world_bind_group, &[]);
+            cpass.set_bind_group(2, &self.static_params_bind_group, &[]);
+            let (x, y) = self.size.slime_workgroups();
+            cpass.dispatch(x, y, 1);
+        }
+        if !self.food_sources.is_empty() {
+            let mut cpass = encoder.begin_compute_pass(&Default::default());
+            cpass.set_pipeline(&self.food_pipeline);
+            cpass.set_bind_group(0, &self.food_bind_group, &[]);
+            cpass.set_bind_group(1, &self.world_bind_group, &[]);
+            cpass.set_bind_group(2, &self.static_params_bind_group, &[]);
+            let groups = (self.food_sources.len() as u32).div_ceil(sizing::SLIME_WORKGROUP_SIZE);
+            cpass.dispatch(groups, 1, 1);
+        }
+        {
+            let mut cpass = encoder.begin_compute_pass(&Default::default());
+            cpass.set_pipeline(&self.world_update_pipeline);
+            cpass.set_bind_group(0, &self.world_update_bind_group, &[]);
+            cpass.set_bind_group(1, &self.inverted_world_bind_group, &[]);
+            cpass.set_bind_group(2, &self.static_params_bind_group, &[]);
+            let (x, y) = self.size.world_workgroups();
+            cpass.dispatch(x, y, 1);
+        }
+        {
+            let mut cpass = encoder.begin_compute_pass(&Default::default());
+            cpass.set_pipeline(&self.buf_to_tex_pipeline);
+            cpass.set_bind_group(0, &self.buf_to_tex_bind_group, &[]);
+            cpass.set_bind_group(1, &self.inverted_world_bind_group, &[]);
+            cpass.set_bind_group(2, &self.static_params_bind_group, &[]);
+            let (x, y) = self.size.world_workgroups();
+            cpass.dispatch(x, y, 1);
+        }
+        encoder.copy_buffer_to_buffer(
+            &self.current_world_buffer,
+            0,
+            &self.next_world_buffer,
+            0,
+            self.size.world_buffer_size(),
+        );
+    }
+
+    /// Runs one step on its own, for the headless commands.
+    pub fn step(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        delta_time: f32,
+        params: &SimParams,
+    ) {
+        let mut encoder = device.create_command_encoder(&Default::default());
+        self.encode_frame(queue, &mut encoder, delta_time, params, 0);
+        queue.submit(Some(encoder.finish()));
+    }
+
+    /// Puts the slimes back where they started, clears the trail and refills the food.
+    pub fn reset(&self, queue: &wgpu::Queue) {
+        let empty_world = vec![0u8; self.size.world_buffer_size() as usize];
+        queue.write_buffer(&self.slimes_buffer, 0, bytemuck::cast_slice(&self.slimes));
+        queue.write_buffer(&self.current_world_buffer, 0, &empty_world);
+        queue.write_buffer(&self.next_world_buffer, 0, &empty_world);
+        self.refill_food(queue);
+        queue.submit(None);
+    }
+
+    pub fn refill_food(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(
+            &self.foods_buffer,
+            0,
+            bytemuck::cast_slice(&self.initial_food),
+        );
+    }
+
+    /// Replaces the trail with `world`, laid out like `current_world_buffer`.
+    pub fn write_world(&self, queue: &wgpu::Queue, world: &[f32]) {
+        queue.write_buffer(&self.current_world_buffer, 0, bytemuck::cast_slice(world));
+        queue.write_buffer(&self.next_world_buffer, 0, bytemuck::cast_slice(world));
+    }
+
+    /// Replaces the slimes on the GPU with `slimes`, filling the rest of the buffer with dead
+    /// ones. Slimes past the buffer are left out.
+    pub fn write_slimes(&self, queue: &wgpu::Queue, slimes: &[Slime]) {
+        let mut all = slimes[..slimes.len().min(self.size.num_slimes as usize)].to_vec();
+        all.resize(self.size.num_slimes as usize, bytemuck::Zeroable::zeroed());
+        queue.write_buffer(&self.slimes_buffer, 0, bytemuck::cast_slice(&all));
+    }
+
+    /// Places the world at `origin` in a picture of `size` pixels, for the colour gradient.
+    pub fn set_view(&self, queue: &wgpu::Queue, origin: (f32, f32), size: (u32, u32)) {
+        queue.write_buffer(
+            &self.view_buffer,
+            0,
+            bytemuck::cast_slice(&[WorldView::new(origin, size)]),
+        );
+    }
+
+    /// Grows or shrinks the swarm to `count` slimes. Existing slimes keep their state, new ones
+    /// are spawned from `params`.
+    pub fn set_slime_count(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        adapter_name: &str,
+        count: u32,
+        params: &SimParams,
+    ) -> Result<(), SizeError> {
+        let new_size = SimSize {
+            num_slimes: count.max(1),
+            ..self.size
+        };
+        new_size.required_limits(adapter_name, &device.limits())?;
+        let old_count = self.size.num_slimes;
+        let new_count = new_size.num_slimes as usize;
+        if new_count > self.slimes.len() {
+            let extra = Slime::new_swarm(
+                new_count - self.slimes.len(),
+                self.size.world,
+                &params.swarm.species_weights,
+                &params.species,
+                &mut self.rng,
+            );
+            self.slimes.extend(extra);
+        }
+        self.slimes.truncate(new_count);
+        self.slimes_buffer =
+            swarm::resize_buffer(device, queue, &self.slimes_buffer, old_count, &self.slimes);
+        self.size = new_size;
+        self.free_list_buffer = create_free_list_buffer(device, &self.size);
+        self.slime_move_bind_group = create_slime_move_bind_group(
+            device,
+            &self.slime_move_bind_group_layout,
+            [
+                &self.slime_move_params_buffer,
+                &self.slimes_buffer,
+                &self.species_params_buffer,
+                &self.free_list_buffer,
+                &self.lifecycle_params_buffer,
+                &self.interactions_buffer,
+                &self.genetics_params_buffer,
+            ],
+        );
+        queue.write_buffer(
+            &self.static_params_buffer,
+            0,
+            bytemuck::cast_slice(&[StaticGlobalParams::new(&self.size)]),
+        );
+        Ok(())
+    }
+
+    /// Reassigns the species of every slime from `weights`, on the GPU and for resets.
+    pub fn rebalance_species(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        weights: &[f32; NUM_SPECIES],
+    ) {
+        let mut current = self.read_slimes(device, queue, self.size.num_slimes);
+        swarm::assign_species(&mut current, weights, &mut self.rng);
+        queue.write_buffer(&self.slimes_buffer, 0, bytemuck::cast_slice(&current));
+        // Resetting should keep the new mix too
+        swarm::assign_species(&mut self.slimes, weights, &mut self.rng);
+    }
+
+    /// Gives every slime the genes of its species' parameters, on the GPU and for resets.
+    pub fn reset_genes(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, params: &SimParams) {
+        let species = &params.species.species;
+        let mut current = self.read_slimes(device, queue, self.size.num_slimes);
+        genes::reset(&mut current, species);
+        queue.write_buffer(&self.slimes_buffer, 0, bytemuck::cast_slice(&current));
+        genes::reset(&mut self.slimes, species);
+    }
+
+    /// The current state of the first `count` slimes.
+    pub fn read_slimes(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        count: u32,
+    ) -> Vec<Slime> {
+        swarm::read_back(
+            device,
+            queue,
+            &self.slimes_buffer,
+            count.min(self.size.num_slimes),
+        )
+    }
+
+    /// Copies the world texture back from the GPU as tightly packed RGBA rows. Blocks until the
+    /// GPU has caught up.
+    pub fn capture(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<u8> {
+        let (width, height) = self.size.world;
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.world_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &self.world_texture_copy_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(padded_bytes_per_row(width)),
+                    rows_per_image: NonZeroU32::new(height),
+                },
+            },
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let buffer_slice = self.world_texture_copy_buffer.slice(..);
+        let mapping = buffer_slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        pollster::block_on(mapping).unwrap();
+        // Strip the row padding, the image encoder wants tightly packed rows
+        let row_size = (width * 4) as usize;
+        let padded_row_size = padded_bytes_per_row(width) as usize;
+        let mut data = Vec::with_capacity(row_size * height as usize);
+        for row in buffer_slice.get_mapped_range().chunks(padded_row_size) {
+            data.extend_from_slice(&row[..row_size]);
+        }
+        self.world_texture_copy_buffer.unmap();
+        data
+    }
+
+    /// Reloads the compute shader `path`. Returns false for shaders the simulation doesn't use.
+    pub fn reload_shader(
+        &mut self,
+        path: &str,
+        device: &wgpu::Device,
+        shader_loader: &ShaderLoader,
+        errors: &mut ShaderErrors,
+    ) -> bool {
+        let (pipeline, layout, interface) = match path {
+            MOVE_SLIMES_SHADER => (
+                &mut self.slime_move_pipeline,
+                &self.slime_move_compute_pipeline_layout,
+                layouts::MOVE_SLIMES,
+            ),
+            LIFECYCLE_SHADER => (
+                &mut self.lifecycle_pipeline,
+                &self.slime_move_compute_pipeline_layout,
+                layouts::LIFECYCLE,
+            ),
+            FOOD_SHADER => (
+                &mut self.food_pipeline,
+                &self.food_compute_pipeline_layout,
+                layouts::FOOD_SOURCES,
+            ),
+            UPDATE_WORLD_SHADER => (
+                &mut self.world_update_pipeline,
+                &self.world_update_compute_pipeline_layout,
+                layouts::UPDATE_WORLD,
+            ),
+            WORLD_TO_TEX_SHADER => (
+                &mut self.buf_to_tex_pipeline,
+                &self.buf_to_tex_compute_pipeline_layout,
+                layouts::WORLD_TO_TEX,
+            ),
+            _ => return false,
+        };
+        reload(pipeline, path, errors, || {
+            shader_loader.load_pipeline(path, device, layout, interface)
+        });
+        true
+    }
+}
+
+fn create_world_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    input: &wgpu::Buffer,
+    output: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: input.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: output.as_entire_binding(),
+            },
+        ],
+    })
+}
+
+/// Recreated whenever the slime count changes and the slime buffer is reallocated.
+/// `buffers` in binding order: move params, slimes, species params, free list, lifecycle params,
+/// interactions and genetics params.
+fn create_slime_move_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    buffers: [&wgpu::Buffer; 7],
+) -> wgpu::BindGroup {
+    let entries: Vec<_> = buffers
+        .iter()
+        .enumerate()
+        .map(|(binding, buffer)| wgpu::BindGroupEntry {
+            binding: binding as u32,
+            resource: buffer.as_entire_binding(),
+        })
+        .collect();
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout,
+        entries: &entries,
+    })
+}
+
+fn create_free_list_buffer(device: &wgpu::Device, size: &SimSize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: size.free_list_buffer_size(),
+        usage: BufferUsages::COPY_DST | BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    })
+}
@@ -0,0 +1,177 @@
+//! Buffer and dispatch sizes of the simulation, worked out up front and checked against what
+//! the adapter supports, so an oversized world fails with a suggestion instead of a wgpu panic.
+
+use std::fmt;
+
+use crate::params::NUM_SPECIES;
+use crate::Slime;
+
+/// WebGPU's limit on workgroups per dispatch dimension. wgpu 0.11 doesn't report it in
+/// `Limits`, but every backend enforces at least this one.
+pub const MAX_WORKGROUPS_PER_DIMENSION: u32 = 65535;
+/// `workgroup_size` of `move_slimes.wgsl`.
+pub const SLIME_WORKGROUP_SIZE: u32 = 64;
+/// `workgroup_size` of `update_world.wgsl` and `world_to_tex.wgsl`, in each axis.
+pub const WORLD_WORKGROUP_SIZE: u32 = 8;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SimSize {
+    pub world: (u32, u32),
+    pub num_slimes: u32,
+}
+
+/// Everything about a `SimSize` the device can't handle.
+#[derive(Debug)]
+pub struct SizeError {
+    pub adapter: String,
+    pub problems: Vec<String>,
+}
+
+impl fmt::Display for SizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the simulation is too large for {}:", self.adapter)?;
+        for problem in &self.problems {
+            write!(f, "\n  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SizeError {}
+
+impl SimSize {
+    /// Size of one world buffer, an `f32` per pixel and species.
+    pub fn world_buffer_size(&self) -> u64 {
+        self.world.0 as u64 * self.world.1 as u64 * NUM_SPECIES as u64 * 4
+    }
+
+    pub fn slimes_buffer_size(&self) -> u64 {
+        self.num_slimes as u64 * std::mem::size_of::<Slime>() as u64
+    }
+
+    /// The free list of dead slimes, an `i32` count followed by a slot per slime.
+    pub fn free_list_buffer_size(&self) -> u64 {
+        4 + self.num_slimes as u64 * 4
+    }
+
+    /// Workgroups for the per-slime shaders. Counts that don't fit in one dimension wrap into
+    /// rows, and the last workgroup may be partly past the end of the buffer.
+    pub fn slime_workgroups(&self) -> (u32, u32) {
+        let groups = self.num_slimes.div_ceil(SLIME_WORKGROUP_SIZE);
+        let x = groups.clamp(1, MAX_WORKGROUPS_PER_DIMENSION);
+        (x, groups.div_ceil(x))
+    }
+
+    /// Slimes handled by one row of the slime dispatch, which the shader needs to turn its
+    /// 2D invocation id back into an index.
+    pub fn slimes_per_row(&self) -> u32 {
+        self.slime_workgroups().0 * SLIME_WORKGROUP_SIZE
+    }
+
+    /// Levels in the full mip chain of the world texture, down to 1x1.
+    pub fn mip_levels(&self) -> u32 {
+        32 - self.world.0.max(self.world.1).max(1).leading_zeros()
+    }
+
+    /// Workgroups for the per-pixel shaders. Edge workgroups overhang worlds that aren't a
+    /// multiple of the workgroup size, the shaders skip pixels outside the world.
+    pub fn world_workgroups(&self) -> (u32, u32) {
+        (
+            self.world.0.div_ceil(WORLD_WORKGROUP_SIZE),
+            self.world.1.div_ceil(WORLD_WORKGROUP_SIZE),
+        )
+    }
+
+    /// The limits to request: the defaults, with the buffer and texture sizes raised to what
+    /// the adapter supports. Fails if `supported` isn't enough for this size.
+    pub fn required_limits(
+        &self,
+        adapter: &str,
+        supported: &wgpu::Limits,
+    ) -> Result<wgpu::Limits, SizeError> {
+        let mut problems = Vec::new();
+
+        let max_binding = supported.max_storage_buffer_binding_size as u64;
+        if self.world_buffer_size() > max_binding {
+            let (w, h) = self.largest_world(max_binding);
+            problems.push(format!(
+                "a {}x{} world needs {} per buffer, but storage buffers are limited to {}. \
+                 The largest world with this aspect ratio is {}x{}",
+                self.world.0,
+                self.world.1,
+                mib(self.world_buffer_size()),
+                mib(max_binding),
+                w,
+                h
+            ));
+        }
+        if self.slimes_buffer_size() > max_binding {
+            problems.push(format!(
+                "{} slimes need {}, but storage buffers are limited to {}. Use at most {} slimes",
+                self.num_slimes,
+                mib(self.slimes_buffer_size()),
+                mib(max_binding),
+                max_binding / std::mem::size_of::<Slime>() as u64
+            ));
+        }
+
+        let max_dim = supported.max_texture_dimension_2d;
+        if self.world.0 > max_dim || self.world.1 > max_dim {
+            problems.push(format!(
+                "a {}x{} world doesn't fit in a texture, which can be at most {}x{}",
+                self.world.0, self.world.1, max_dim, max_dim
+            ));
+        }
+
+        let (wx, wy) = self.world_workgroups();
+        if wx.max(wy) > MAX_WORKGROUPS_PER_DIMENSION {
+            problems.push(format!(
+                "a {}x{} world needs {}x{} workgroups, but a dispatch can have at most {} \
+                 in each dimension",
+                self.world.0, self.world.1, wx, wy, MAX_WORKGROUPS_PER_DIMENSION
+            ));
+        }
+
+        if !problems.is_empty() {
+            return Err(SizeError {
+                adapter: adapter.to_owned(),
+                problems,
+            });
+        }
+
+        // Ask for everything the adapter has, so the slime count can grow at runtime.
+        let defaults = wgpu::Limits::default();
+        Ok(wgpu::Limits {
+            max_storage_buffer_binding_size: defaults
+                .max_storage_buffer_binding_size
+                .max(supported.max_storage_buffer_binding_size),
+            max_texture_dimension_2d: defaults
+                .max_texture_dimension_2d
+                .max(supported.max_texture_dimension_2d),
+            ..defaults
+        })
+    }
+
+    /// The largest world with the same aspect ratio whose buffer fits in `max_bytes`.
+    fn largest_world(&self, max_bytes: u64) -> (u32, u32) {
+        let scale = (max_bytes as f64 / self.world_buffer_size() as f64).sqrt();
+        let fit = |n: u32| (n as f64 * scale) as u32;
+        (fit(self.world.0), fit(self.world.1))
+    }
+
+    /// Size of the buffer the world texture is copied into for screenshots and recording.
+    pub fn readback_buffer_size(&self) -> u64 {
+        padded_bytes_per_row(self.world.0) as u64 * self.world.1 as u64
+    }
+}
+
+/// Bytes per row of an RGBA8 texture readback. Texture to buffer copies need rows aligned to
+/// 256 bytes, so worlds whose width isn't a multiple of 64 get padded rows.
+pub fn padded_bytes_per_row(width: u32) -> u32 {
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    (width * 4).div_ceil(align) * align
+}
+
+fn mib(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
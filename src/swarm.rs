@@ -0,0 +1,229 @@
+//! Changing the slimes while the simulation runs: growing or shrinking the slime buffer and
+//! reassigning species.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+
+use rand::distributions::WeightedIndex;
+use rand::Rng;
+use wgpu::BufferUsages;
+
+use crate::params::NUM_SPECIES;
+use crate::Slime;
+
+const SLIME_SIZE: u64 = std::mem::size_of::<Slime>() as u64;
+
+/// Usages of the slime buffer. `COPY_SRC` lets it be copied into a resized buffer or read back.
+pub const SLIMES_BUFFER_USAGES: BufferUsages = BufferUsages::COPY_DST
+    .union(BufferUsages::COPY_SRC)
+    .union(BufferUsages::STORAGE);
+
+/// Distribution of species for new slimes, `None` when every weight is zero.
+pub fn species_distribution(weights: &[f32; NUM_SPECIES]) -> Option<WeightedIndex<f32>> {
+    WeightedIndex::new(weights).ok()
+}
+
+/// Creates a buffer for `initial.len()` slimes. Slimes that already exist keep their current
+/// state from `old`, slimes past `old_count` start from `initial`.
+pub fn resize_buffer(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    old: &wgpu::Buffer,
+    old_count: u32,
+    initial: &[Slime],
+) -> wgpu::Buffer {
+    let kept = initial.len().min(old_count as usize);
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: initial.len() as u64 * SLIME_SIZE,
+        usage: SLIMES_BUFFER_USAGES,
+        mapped_at_creation: false,
+    });
+    if kept < initial.len() {
+        queue.write_buffer(
+            &buffer,
+            kept as u64 * SLIME_SIZE,
+            bytemuck::cast_slice(&initial[kept..]),
+        );
+    }
+    let mut encoder = device.create_command_encoder(&Default::default());
+    encoder.copy_buffer_to_buffer(old, 0, &buffer, 0, kept as u64 * SLIME_SIZE);
+    queue.submit(Some(encoder.finish()));
+    buffer
+}
+
+/// Reads the current state of the first `count` slimes back from the GPU. Blocks until the
+/// GPU has caught up.
+pub fn read_back(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+    count: u32,
+) -> Vec<Slime> {
+    read_ranges(device, queue, buffer, &[(0, count)])
+}
+
+/// Slimes read in one piece by `SampleReader`. Long enough to keep the copies few, short enough
+/// that the runs are spread over the whole buffer.
+const SAMPLE_RUN: u32 = 256;
+
+/// Ranges of about `count` of the `num_slimes` slimes, in short runs evenly spaced across the
+/// whole buffer so the sample doesn't follow spawn order.
+fn sample_ranges(num_slimes: u32, count: u32) -> Vec<(u32, u32)> {
+    if count >= num_slimes {
+        return vec![(0, num_slimes)];
+    }
+    let runs = count.div_ceil(SAMPLE_RUN);
+    let run = count / runs;
+    (0..runs)
+        .map(|i| ((i as u64 * num_slimes as u64 / runs as u64) as u32, run))
+        .collect()
+}
+
+/// Copies the `(first, count)` ranges of slimes from `buffer` into `staging`, one after the other.
+fn copy_ranges(
+    encoder: &mut wgpu::CommandEncoder,
+    buffer: &wgpu::Buffer,
+    staging: &wgpu::Buffer,
+    ranges: &[(u32, u32)],
+) {
+    let mut offset = 0;
+    for &(first, count) in ranges {
+        let bytes = count as u64 * SLIME_SIZE;
+        encoder.copy_buffer_to_buffer(buffer, first as u64 * SLIME_SIZE, staging, offset, bytes);
+        offset += bytes;
+    }
+}
+
+fn ranges_size(ranges: &[(u32, u32)]) -> u64 {
+    ranges.iter().map(|&(_, n)| n as u64).sum::<u64>() * SLIME_SIZE
+}
+
+type Mapping = Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>> + Send>>;
+
+enum SampleState {
+    Idle,
+    /// The copy is recorded, waiting for `map` once it is submitted.
+    Copied,
+    Mapping(Mapping),
+}
+
+/// Reads samples of the slimes back from the GPU without stalling the frame. The copy is
+/// recorded into the frame's encoder, mapped once the frame is submitted and picked up by `poll`
+/// on a later frame, so the sample lags the swarm by a frame or two.
+pub struct SampleReader {
+    count: u32,
+    /// Staging buffer kept between reads, with the slime count its ranges were made for.
+    staging: Option<(wgpu::Buffer, u32)>,
+    ranges: Vec<(u32, u32)>,
+    state: SampleState,
+}
+
+impl SampleReader {
+    /// A reader of about `count` slimes at a time.
+    pub fn new(count: u32) -> Self {
+        Self {
+            count,
+            staging: None,
+            ranges: Vec::new(),
+            state: SampleState::Idle,
+        }
+    }
+
+    /// Whether a sample has been requested and not picked up yet.
+    pub fn is_busy(&self) -> bool {
+        !matches!(self.state, SampleState::Idle)
+    }
+
+    /// Records copying a sample of the `num_slimes` slimes in `buffer` into `encoder`. Call `map`
+    /// once the encoder is submitted. Does nothing while the last sample is still being read.
+    pub fn copy(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        buffer: &wgpu::Buffer,
+        num_slimes: u32,
+    ) {
+        if self.is_busy() {
+            return;
+        }
+        if !matches!(self.staging, Some((_, n)) if n == num_slimes) {
+            self.ranges = sample_ranges(num_slimes, self.count);
+            let staging = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Slime sample staging buffer"),
+                size: ranges_size(&self.ranges),
+                usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            });
+            self.staging = Some((staging, num_slimes));
+        }
+        let (staging, _) = self.staging.as_ref().unwrap();
+        copy_ranges(encoder, buffer, staging, &self.ranges);
+        self.state = SampleState::Copied;
+    }
+
+    /// Starts mapping the sample after the encoder `copy` recorded into has been submitted.
+    pub fn map(&mut self) {
+        if let (SampleState::Copied, Some((staging, _))) = (&self.state, &self.staging) {
+            let mapping = staging.slice(..).map_async(wgpu::MapMode::Read);
+            self.state = SampleState::Mapping(Box::pin(mapping));
+        }
+    }
+
+    /// The sample once the GPU has finished copying it, without waiting for it.
+    pub fn poll(&mut self, device: &wgpu::Device) -> Option<Vec<Slime>> {
+        let SampleState::Mapping(mapping) = &mut self.state else {
+            return None;
+        };
+        device.poll(wgpu::Maintain::Poll);
+        let result = match mapping
+            .as_mut()
+            .poll(&mut Context::from_waker(Waker::noop()))
+        {
+            Poll::Ready(result) => result,
+            Poll::Pending => return None,
+        };
+        self.state = SampleState::Idle;
+        let (staging, _) = self.staging.as_ref()?;
+        result.ok()?;
+        let slimes = bytemuck::cast_slice(&staging.slice(..).get_mapped_range()).to_vec();
+        staging.unmap();
+        Some(slimes)
+    }
+}
+
+/// Reads the `(first, count)` ranges of slimes back from the GPU, one after the other.
+fn read_ranges(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+    ranges: &[(u32, u32)],
+) -> Vec<Slime> {
+    let staging = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: ranges_size(ranges),
+        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&Default::default());
+    copy_ranges(&mut encoder, buffer, &staging, ranges);
+    queue.submit(Some(encoder.finish()));
+
+    let slice = staging.slice(..);
+    let mapping = slice.map_async(wgpu::MapMode::Read);
+    device.poll(wgpu::Maintain::Wait);
+    pollster::block_on(mapping).unwrap();
+    let slimes = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+    staging.unmap();
+    slimes
+}
+
+/// Gives every slime a new species drawn from `weights`. Does nothing if all weights are zero.
+pub fn assign_species(slimes: &mut [Slime], weights: &[f32; NUM_SPECIES], rng: &mut impl Rng) {
+    if let Some(species) = species_distribution(weights) {
+        for slime in slimes {
+            slime.species = rng.sample(&species) as u32;
+        }
+    }
+}
@@ -0,0 +1,470 @@
+//! Tiled rendering for worlds too large for one GPU buffer, such as 16384x16384 prints.
+//!
+//! The world is cut into square tiles kept on the CPU. One simulation the size of a tile plus a
+//! halo on every side runs each tile in turn: it is loaded with the tile's trail and slimes, the
+//! neighbours' trail and slimes that fall in the halo, and stepped for as many frames as it
+//! takes for the missing world past the halo to reach the tile. Then only the tile itself is
+//! kept, and its slimes are binned again into the tiles they moved to. The finished image is
+//! streamed to disk one row of tiles at a time, so it never has to fit in memory either.
+//!
+//! Food sources and the lifecycle model aren't supported, slimes only live in one tile at a time
+//! and the number of slimes stays fixed.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::genes::Genes;
+use crate::network;
+use crate::params::{SimParams, NUM_SPECIES};
+use crate::shaders::ShaderLoader;
+use crate::simulation::Simulation;
+use crate::sizing::SimSize;
+use crate::{swarm, Slime};
+
+const DELTA_TIME: f32 = 1.0 / 60.0;
+/// Slime slots in the tile simulation, relative to the average number of slimes in a tile and
+/// its halo. Slimes gather into strands, so some tiles hold more than their share.
+const SLIME_HEADROOM: f64 = 2.0;
+
+/// How the world is cut up.
+pub struct Tiling {
+    pub world: (u32, u32),
+    /// Side of a tile, in pixels.
+    pub tile: u32,
+    /// Pixels of the neighbouring tiles simulated on each side of a tile, at most `tile`.
+    pub halo: u32,
+    /// Slimes in the whole world.
+    pub num_slimes: u64,
+}
+
+/// A rectangle of the world, in pixels.
+#[derive(Debug, Clone, Copy)]
+struct Rect {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl Rect {
+    fn contains(&self, (x, y): (f32, f32)) -> bool {
+        x >= self.x as f32
+            && y >= self.y as f32
+            && x < (self.x + self.width) as f32
+            && y < (self.y + self.height) as f32
+    }
+}
+
+struct Tile {
+    rect: Rect,
+    /// A plane of `rect.width * rect.height` trail values per species, like the world buffers.
+    world: Vec<f32>,
+    /// Slimes inside `rect`, in world coordinates.
+    slimes: Vec<Slime>,
+}
+
+impl Tiling {
+    pub fn new(world: (u32, u32), tile: u32, halo: u32, num_slimes: u64) -> Self {
+        let tile = tile.clamp(1, world.0.max(world.1));
+        Tiling {
+            world,
+            tile,
+            halo: halo.min(tile),
+            num_slimes,
+        }
+    }
+
+    fn columns(&self) -> u32 {
+        self.world.0.div_ceil(self.tile)
+    }
+
+    fn rows(&self) -> u32 {
+        self.world.1.div_ceil(self.tile)
+    }
+
+    /// Side of the simulation a tile runs in.
+    fn side(&self) -> u32 {
+        self.tile + 2 * self.halo
+    }
+
+    /// Size of the simulation the tiles run in, which the device has to support.
+    pub fn sim_size(&self) -> SimSize {
+        let area = self.world.0 as f64 * self.world.1 as f64;
+        let side = self.side() as f64;
+        let slimes = self.num_slimes as f64 / area * side * side * SLIME_HEADROOM;
+        SimSize {
+            world: (self.side(), self.side()),
+            num_slimes: (slimes.ceil() as u32).max(1),
+        }
+    }
+
+    /// Approximate memory the tiles take on the CPU, in bytes.
+    pub fn memory(&self) -> u64 {
+        let world = self.world.0 as u64 * self.world.1 as u64 * NUM_SPECIES as u64 * 4;
+        // Slimes are binned into new lists while the old ones are still needed
+        let slimes = self.num_slimes * std::mem::size_of::<Slime>() as u64 * 2;
+        world + slimes
+    }
+
+    /// Printed before a tiled render starts.
+    pub fn describe(&self, params: &SimParams) -> String {
+        format!(
+            "{}x{} tiles of {} pixels with a {} pixel halo, exchanging every {} frames, about {} MiB of memory",
+            self.columns(),
+            self.rows(),
+            self.tile,
+            self.halo,
+            self.exchange_interval(params),
+            self.memory() / (1024 * 1024)
+        )
+    }
+
+    /// Frames a tile can run on its own before the missing world past its halo reaches the tile.
+    /// Each frame trails spread by the blur radius and slimes move by their speed, and slimes
+    /// sense as far as their sensor distance.
+    fn exchange_interval(&self, params: &SimParams) -> u64 {
+        let species = &params.species.species;
+        let speed = species.iter().map(|s| s.move_speed).fold(0.0, f32::max);
+        let reach = species
+            .iter()
+            .map(|s| s.sensor_distance)
+            .fold(0.0, f32::max);
+        let per_frame = speed + params.world.blur_radius.max(0) as f32;
+        ((self.halo as f32 - reach) / per_frame.max(1.0))
+            .floor()
+            .max(1.0) as u64
+    }
+
+    fn rect(&self, index: usize) -> Rect {
+        let (column, row) = (index as u32 % self.columns(), index as u32 / self.columns());
+        let (x, y) = (column * self.tile, row * self.tile);
+        Rect {
+            x,
+            y,
+            width: self.tile.min(self.world.0 - x),
+            height: self.tile.min(self.world.1 - y),
+        }
+    }
+
+    /// The tiles next to a tile and the tile itself, the only ones its halo can reach.
+    fn neighbours(&self, index: usize) -> impl Iterator<Item = usize> {
+        let (columns, rows) = (self.columns() as i64, self.rows() as i64);
+        let (column, row) = (index as i64 % columns, index as i64 / columns);
+        (-1..=1)
+            .flat_map(move |dy| (-1..=1).map(move |dx| (column + dx, row + dy)))
+            .filter(move |&(x, y)| x >= 0 && y >= 0 && x < columns && y < rows)
+            .map(move |(x, y)| (y * columns + x) as usize)
+    }
+
+    /// Index of the tile holding a position in the world.
+    fn tile_at(&self, (x, y): (f32, f32)) -> usize {
+        let column = ((x.max(0.0) as u32) / self.tile).min(self.columns() - 1);
+        let row = ((y.max(0.0) as u32) / self.tile).min(self.rows() - 1);
+        (row * self.columns() + column) as usize
+    }
+
+    /// Empty tiles with slimes spread evenly over the world.
+    fn spawn(&self, params: &SimParams, rng: &mut impl Rng) -> Vec<Tile> {
+        let species = swarm::species_distribution(&params.swarm.species_weights);
+        let density = self.num_slimes as f64 / (self.world.0 as f64 * self.world.1 as f64);
+        (0..(self.columns() * self.rows()) as usize)
+            .map(|index| {
+                let rect = self.rect(index);
+                let count = (density * rect.width as f64 * rect.height as f64).round() as usize;
+                let slimes = (0..count)
+                    .map(|_| {
+                        let species = species.as_ref().map_or(0, |s| rng.sample(s));
+                        Slime {
+                            pos: [
+                                rng.gen_range(rect.x as f32..(rect.x + rect.width) as f32),
+                                rng.gen_range(rect.y as f32..(rect.y + rect.height) as f32),
+                            ],
+                            heading: rng.gen_range(0.0..std::f32::consts::PI * 2.0),
+                            species: species as u32,
+                            energy: 0.5,
+                            genes: Genes::from_params(&params.species.species[species]),
+                            _pad: 0.0,
+                        }
+                    })
+                    .collect();
+                Tile {
+                    rect,
+                    world: vec![0.0; rect.width as usize * rect.height as usize * NUM_SPECIES],
+                    slimes,
+                }
+            })
+            .collect()
+    }
+
+    /// Top left corner of the tile simulation for a tile, in world coordinates.
+    fn origin(&self, rect: Rect) -> (i64, i64) {
+        (
+            rect.x as i64 - self.halo as i64,
+            rect.y as i64 - self.halo as i64,
+        )
+    }
+
+    /// Copies between the planes of a tile and those of the tile simulation at `origin`, in
+    /// whichever direction `to_sim` says. Only the part of the tile in the simulation is copied.
+    fn copy_world(&self, tile: &mut Tile, sim_world: &mut [f32], origin: (i64, i64), to_sim: bool) {
+        let side = self.side() as i64;
+        let rect = tile.rect;
+        let x0 = (rect.x as i64).max(origin.0);
+        let x1 = ((rect.x + rect.width) as i64).min(origin.0 + side);
+        let y0 = (rect.y as i64).max(origin.1);
+        let y1 = ((rect.y + rect.height) as i64).min(origin.1 + side);
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+        let len = (x1 - x0) as usize;
+        let tile_plane = rect.width as usize * rect.height as usize;
+        let sim_plane = (side * side) as usize;
+        for species in 0..NUM_SPECIES {
+            for y in y0..y1 {
+                let src = species * tile_plane
+                    + (y - rect.y as i64) as usize * rect.width as usize
+                    + (x0 - rect.x as i64) as usize;
+                let dst = species * sim_plane + ((y - origin.1) * side + (x0 - origin.0)) as usize;
+                if to_sim {
+                    sim_world[dst..dst + len].copy_from_slice(&tile.world[src..src + len]);
+                } else {
+                    tile.world[src..src + len].copy_from_slice(&sim_world[dst..dst + len]);
+                }
+            }
+        }
+    }
+
+    /// Runs every tile for `frames` frames. With `image`, each tile's picture is captured at the
+    /// end and a row of tiles is written out as soon as it is done.
+    #[allow(clippy::too_many_arguments)]
+    fn advance(
+        &self,
+        sim: &mut Simulation,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        tiles: &mut [Tile],
+        frames: u64,
+        params: &SimParams,
+        mut image: Option<&mut StripWriter>,
+    ) -> Result<(), Box<dyn Error>> {
+        let side = self.side() as i64;
+        let sim_rect = |origin: (i64, i64)| {
+            move |(x, y): (f32, f32)| {
+                x >= origin.0 as f32
+                    && y >= origin.1 as f32
+                    && x < (origin.0 + side) as f32
+                    && y < (origin.1 + side) as f32
+            }
+        };
+        let capacity = sim.size.num_slimes as usize;
+        // New trails wait here until every neighbour has read the old ones for its halo
+        let mut finished: Vec<Option<Vec<f32>>> = vec![None; tiles.len()];
+        let mut binned: Vec<Vec<Slime>> = vec![Vec::new(); tiles.len()];
+        let mut strip = Vec::new();
+        let mut overflow = 0;
+
+        for index in 0..tiles.len() {
+            let rect = tiles[index].rect;
+            let origin = self.origin(rect);
+            let mut world = vec![0.0; (side * side) as usize * NUM_SPECIES];
+            for neighbour in self.neighbours(index) {
+                self.copy_world(&mut tiles[neighbour], &mut world, origin, true);
+            }
+
+            // The tile's own slimes first, so they can be told apart when read back, then the
+            // neighbours' slimes in the halo
+            let local = |slime: &Slime| Slime {
+                pos: [
+                    slime.pos[0] - origin.0 as f32,
+                    slime.pos[1] - origin.1 as f32,
+                ],
+                ..*slime
+            };
+            let owned = tiles[index].slimes.len().min(capacity);
+            let mut slimes: Vec<Slime> = tiles[index].slimes[..owned].iter().map(local).collect();
+            for neighbour in self.neighbours(index).filter(|&n| n != index) {
+                let in_sim = sim_rect(origin);
+                slimes.extend(
+                    tiles[neighbour]
+                        .slimes
+                        .iter()
+                        .filter(|s| in_sim((s.pos[0], s.pos[1])))
+                        .map(local),
+                );
+            }
+            // Slimes that don't fit sit this round out where they are
+            for slime in &tiles[index].slimes[owned..] {
+                binned[index].push(*slime);
+                overflow += 1;
+            }
+
+            sim.write_world(queue, &world);
+            sim.write_slimes(queue, &slimes);
+            sim.set_view(queue, (origin.0 as f32, origin.1 as f32), self.world);
+            for _ in 0..frames {
+                sim.step(device, queue, DELTA_TIME, params);
+            }
+
+            let world = network::read_world(device, queue, &sim.current_world_buffer, &sim.size);
+            finished[index] = Some(world);
+            for slime in sim.read_slimes(device, queue, owned as u32) {
+                let slime = self.reflect(Slime {
+                    pos: [
+                        slime.pos[0] + origin.0 as f32,
+                        slime.pos[1] + origin.1 as f32,
+                    ],
+                    ..slime
+                });
+                binned[self.tile_at((slime.pos[0], slime.pos[1]))].push(slime);
+            }
+            if let Some(image) = image.as_deref_mut() {
+                strip.push((
+                    rect,
+                    crop(&sim.capture(device, queue), self.side(), self.halo, rect),
+                ));
+                if (index + 1) % self.columns() as usize == 0 {
+                    image.write(&strip)?;
+                    strip.clear();
+                }
+            }
+
+            // Tiles whose neighbours are all done can take their new trail
+            for done in 0..=index {
+                let last = self.neighbours(done).max().unwrap_or(done);
+                if last <= index {
+                    if let Some(mut world) = finished[done].take() {
+                        let origin = self.origin(tiles[done].rect);
+                        self.copy_world(&mut tiles[done], &mut world, origin, false);
+                    }
+                }
+            }
+        }
+        for (tile, slimes) in tiles.iter_mut().zip(binned) {
+            debug_assert!(slimes
+                .iter()
+                .all(|s| tile.rect.contains((s.pos[0], s.pos[1]))));
+            tile.slimes = slimes;
+        }
+        if overflow > 0 {
+            println!(
+                "{} slimes didn't fit in their tile this round and sat it out, try fewer slimes",
+                overflow
+            );
+        }
+        Ok(())
+    }
+
+    /// Bounces slimes that left the world off its edge, like `move_slimes.wgsl` does. Inside a
+    /// tile simulation the world's edge isn't an edge, so they can cross it between exchanges.
+    fn reflect(&self, mut slime: Slime) -> Slime {
+        let (width, height) = (self.world.0 as f32, self.world.1 as f32);
+        if slime.pos[0] < 0.0 || slime.pos[0] >= width {
+            slime.pos[0] = slime.pos[0].clamp(0.0, width - 0.5);
+            slime.heading -= std::f32::consts::PI;
+        }
+        if slime.pos[1] < 0.0 || slime.pos[1] >= height {
+            slime.pos[1] = slime.pos[1].clamp(0.0, height - 0.5);
+            slime.heading = -slime.heading;
+        }
+        slime
+    }
+}
+
+/// The part of a tile simulation's picture covering the tile itself.
+fn crop(rgba: &[u8], side: u32, halo: u32, rect: Rect) -> Vec<u8> {
+    let row = side as usize * 4;
+    let (start, len) = (halo as usize * 4, rect.width as usize * 4);
+    rgba.chunks(row)
+        .skip(halo as usize)
+        .take(rect.height as usize)
+        .flat_map(|line| &line[start..start + len])
+        .copied()
+        .collect()
+}
+
+/// A PNG written a row of tiles at a time.
+struct StripWriter {
+    stream: png::StreamWriter<'static, BufWriter<File>>,
+}
+
+impl StripWriter {
+    fn create(path: &Path, (width, height): (u32, u32)) -> Result<Self, Box<dyn Error>> {
+        let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        Ok(StripWriter {
+            stream: encoder.write_header()?.into_stream_writer(),
+        })
+    }
+
+    /// Writes a row of tiles, left to right, each as tightly packed RGBA rows.
+    fn write(&mut self, tiles: &[(Rect, Vec<u8>)]) -> Result<(), Box<dyn Error>> {
+        let height = tiles.first().map_or(0, |(rect, _)| rect.height as usize);
+        for y in 0..height {
+            for (rect, rgba) in tiles {
+                let row = rect.width as usize * 4;
+                self.stream.write_all(&rgba[y * row..(y + 1) * row])?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), Box<dyn Error>> {
+        Ok(self.stream.finish()?)
+    }
+}
+
+/// Renders `frames` frames of a world cut up by `tiling` and writes the last one to `path`.
+/// `params` has the lifecycle turned off, see the module docs.
+#[allow(clippy::too_many_arguments)]
+pub fn render(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    shader_loader: &ShaderLoader,
+    tiling: &Tiling,
+    params: &SimParams,
+    seed: u64,
+    frames: u64,
+    path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let mut params = *params;
+    params.lifecycle.enabled = 0;
+    let interval = tiling.exchange_interval(&params);
+    let mut sim = Simulation::new(
+        device,
+        shader_loader,
+        tiling.sim_size(),
+        &params,
+        Vec::new(),
+        seed,
+    );
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut tiles = tiling.spawn(&params, &mut rng);
+
+    let frames = frames.max(1);
+    let mut frame = 0;
+    let mut image = None;
+    while frame < frames {
+        let steps = interval.min(frames - frame);
+        frame += steps;
+        if frame == frames {
+            image = Some(StripWriter::create(path, tiling.world)?);
+        }
+        tiling.advance(
+            &mut sim,
+            device,
+            queue,
+            &mut tiles,
+            steps,
+            &params,
+            image.as_mut(),
+        )?;
+        println!("  frame {} / {}", frame, frames);
+    }
+    image.ok_or("nothing rendered")?.finish()
+}
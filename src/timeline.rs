@@ -0,0 +1,387 @@
+//! Keyframed parameter animation. A timeline file lists keyframes that each set one parameter
+//! at a frame or time, and every frame the parameters are interpolated between them. Playback is
+//! by frame number, so a recording of a timeline turns out the same every time.
+
+use std::error::Error;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::audio::AudioReactive;
+use crate::params::SimParams;
+
+/// How a parameter gets from the previous keyframe to the next one.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Easing {
+    /// Holds the previous value and jumps at the keyframe.
+    Step,
+    #[default]
+    Linear,
+    /// Cubic, starting slow.
+    EaseIn,
+    /// Cubic, ending slow.
+    EaseOut,
+    /// Cubic, slow at both ends.
+    EaseInOut,
+}
+
+impl Easing {
+    /// Maps the linear progress `t` between two keyframes, both 0 to 1.
+    fn apply(self, t: f32) -> f32 {
+        match self {
+            Easing::Step => 0.0,
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+        }
+    }
+}
+
+/// A keyframe as written in the file, at either a `frame` or a `time` in seconds.
+#[derive(Debug, Deserialize)]
+struct KeyframeEntry {
+    frame: Option<u64>,
+    time: Option<f64>,
+    /// Parameter path such as `species.0.sensor_distance`, see `SimParams::field`.
+    param: String,
+    value: f32,
+    /// Easing from the previous keyframe of the same parameter into this one.
+    #[serde(default)]
+    easing: Easing,
+}
+
+#[derive(Debug, Deserialize)]
+struct TimelineFile {
+    /// Frames per second used to convert `time` keyframes, and the simulation step while the
+    /// timeline plays.
+    #[serde(default = "default_fps")]
+    fps: f64,
+    /// Starts over after the last keyframe.
+    #[serde(default, rename = "loop")]
+    looping: bool,
+    #[serde(rename = "keyframe", default)]
+    keyframes: Vec<KeyframeEntry>,
+}
+
+fn default_fps() -> f64 {
+    60.0
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Keyframe {
+    frame: u64,
+    value: f32,
+    easing: Easing,
+}
+
+/// The keyframes of one parameter, sorted by frame.
+#[derive(Debug)]
+struct Track {
+    param: String,
+    keyframes: Vec<Keyframe>,
+}
+
+impl Track {
+    fn value(&self, frame: u64) -> f32 {
+        let next = self.keyframes.partition_point(|k| k.frame <= frame);
+        if next == 0 {
+            return self.keyframes[0].value;
+        }
+        let previous = self.keyframes[next - 1];
+        match self.keyframes.get(next) {
+            None => previous.value,
+            Some(next) => {
+                let t = (frame - previous.frame) as f32 / (next.frame - previous.frame) as f32;
+                previous.value + (next.value - previous.value) * next.easing.apply(t)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Timeline {
+    pub fps: f64,
+    looping: bool,
+    tracks: Vec<Track>,
+    /// Frame of the last keyframe.
+    pub length: u64,
+}
+
+impl Timeline {
+    /// Reads a timeline from a TOML file of `[[keyframe]]` tables. Unknown parameters and
+    /// keyframes without a frame or time are errors.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    fn parse(text: &str) -> Result<Self, Box<dyn Error>> {
+        let file: TimelineFile = toml::from_str(text)?;
+        if file.fps <= 0.0 {
+            return Err("fps must be positive".into());
+        }
+        let mut tracks: Vec<Track> = Vec::new();
+        for entry in file.keyframes {
+            let frame = match (entry.frame, entry.time) {
+                (Some(frame), None) => frame,
+                (None, Some(time)) if time >= 0.0 => (time * file.fps).round() as u64,
+                _ => {
+                    return Err(format!(
+                        "keyframe for {} needs either a frame or a time of at least 0",
+                        entry.param
+                    )
+                    .into())
+                }
+            };
+            // Check the path once here instead of failing every frame
+            SimParams::default().set(&entry.param, entry.value)?;
+            let keyframe = Keyframe {
+                frame,
+                value: entry.value,
+                easing: entry.easing,
+            };
+            match tracks.iter_mut().find(|t| t.param == entry.param) {
+                Some(track) => track.keyframes.push(keyframe),
+                None => tracks.push(Track {
+                    param: entry.param,
+                    keyframes: vec![keyframe],
+                }),
+            }
+        }
+        if tracks.is_empty() {
+            return Err("no keyframes".into());
+        }
+        for track in &mut tracks {
+            // Stable, so of two keyframes on the same frame the later one in the file wins
+            track.keyframes.sort_by_key(|k| k.frame);
+            track.keyframes.dedup_by(|later, earlier| {
+                let same = later.frame == earlier.frame;
+                if same {
+                    *earlier = *later;
+                }
+                same
+            });
+        }
+        let length = tracks
+            .iter()
+            .filter_map(|t| t.keyframes.last())
+            .map(|k| k.frame)
+            .max()
+            .unwrap_or(0);
+        Ok(Timeline {
+            fps: file.fps,
+            looping: file.looping,
+            tracks,
+            length,
+        })
+    }
+
+    /// Simulation step for one timeline frame.
+    pub fn delta_time(&self) -> f32 {
+        (1.0 / self.fps) as f32
+    }
+
+    /// Writes the value of every animated parameter at `frame` into `params`. Parameters
+    /// without keyframes keep their values.
+    pub fn apply(&self, frame: u64, params: &mut SimParams) {
+        let frame = if self.looping && self.length > 0 {
+            frame % (self.length + 1)
+        } else {
+            frame
+        };
+        for track in &self.tracks {
+            // The paths were checked when loading
+            let _ = params.set(&track.param, track.value(frame));
+        }
+    }
+}
+
+/// Everything that sets parameters frame by frame: a timeline and audio, with the audio applied
+/// last so it wins where both drive the same parameter.
+#[derive(Default)]
+pub struct Animation {
+    pub timeline: Option<Timeline>,
+    pub audio: Option<AudioReactive>,
+}
+
+impl Animation {
+    pub fn is_empty(&self) -> bool {
+        self.timeline.is_none() && self.audio.is_none()
+    }
+
+    /// Fixed simulation step while animating, at the audio's frame rate if there is audio.
+    pub fn delta_time(&self) -> Option<f32> {
+        match (&self.audio, &self.timeline) {
+            (Some(audio), _) => Some(audio.delta_time()),
+            (None, Some(timeline)) => Some(timeline.delta_time()),
+            (None, None) => None,
+        }
+    }
+
+    /// Frame rate the animation was written for, the audio's if there is audio.
+    pub fn fps(&self) -> Option<f64> {
+        match (&self.audio, &self.timeline) {
+            (Some(audio), _) => Some(audio.fps),
+            (None, Some(timeline)) => Some(timeline.fps),
+            (None, None) => None,
+        }
+    }
+
+    /// Frames until everything has played: the whole audio track, or up to the last keyframe.
+    pub fn length(&self) -> Option<u64> {
+        match (&self.audio, &self.timeline) {
+            (Some(audio), _) => Some(audio.frames),
+            (None, Some(timeline)) => Some(timeline.length + 1),
+            (None, None) => None,
+        }
+    }
+
+    /// Sets the animated parameters for `frame`. Returns false if there is nothing to animate.
+    pub fn apply(&self, frame: u64, params: &mut SimParams) -> bool {
+        if let Some(timeline) = &self.timeline {
+            timeline.apply(frame, params);
+        }
+        if let Some(audio) = &self.audio {
+            audio.apply(frame, params);
+        }
+        !self.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timeline(text: &str) -> Timeline {
+        Timeline::parse(text).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn decay_at(timeline: &Timeline, frame: u64) -> f32 {
+        let mut params = SimParams::default();
+        timeline.apply(frame, &mut params);
+        params.world.decay
+    }
+
+    #[test]
+    fn holds_the_ends_and_interpolates_between() {
+        let timeline = timeline(
+            r#"
+            [[keyframe]]
+            frame = 10
+            param = "world.decay"
+            value = 0.5
+
+            [[keyframe]]
+            frame = 20
+            param = "world.decay"
+            value = 0.9
+            "#,
+        );
+        assert_eq!(timeline.length, 20);
+        assert_eq!(decay_at(&timeline, 0), 0.5);
+        assert_eq!(decay_at(&timeline, 10), 0.5);
+        assert!((decay_at(&timeline, 15) - 0.7).abs() < 1e-6);
+        assert_eq!(decay_at(&timeline, 20), 0.9);
+        assert_eq!(decay_at(&timeline, 1000), 0.9);
+    }
+
+    #[test]
+    fn later_keyframe_on_the_same_frame_wins() {
+        let timeline = timeline(
+            r#"
+            [[keyframe]]
+            frame = 5
+            param = "world.decay"
+            value = 0.1
+
+            [[keyframe]]
+            time = 0.0
+            param = "world.decay"
+            value = 0.2
+
+            [[keyframe]]
+            frame = 5
+            param = "world.decay"
+            value = 0.3
+            "#,
+        );
+        assert_eq!(timeline.tracks[0].keyframes.len(), 2);
+        assert_eq!(decay_at(&timeline, 0), 0.2);
+        assert_eq!(decay_at(&timeline, 5), 0.3);
+    }
+
+    #[test]
+    fn step_holds_until_the_keyframe() {
+        let timeline = timeline(
+            r#"
+            [[keyframe]]
+            frame = 0
+            param = "world.decay"
+            value = 0.5
+
+            [[keyframe]]
+            frame = 10
+            param = "world.decay"
+            value = 0.9
+            easing = "step"
+            "#,
+        );
+        assert_eq!(decay_at(&timeline, 9), 0.5);
+        assert_eq!(decay_at(&timeline, 10), 0.9);
+    }
+
+    #[test]
+    fn loops_after_the_last_keyframe() {
+        let timeline = timeline(
+            r#"
+            loop = true
+
+            [[keyframe]]
+            frame = 0
+            param = "world.decay"
+            value = 0.5
+
+            [[keyframe]]
+            frame = 4
+            param = "world.decay"
+            value = 0.9
+            "#,
+        );
+        assert_eq!(decay_at(&timeline, 4), 0.9);
+        assert_eq!(decay_at(&timeline, 5), 0.5);
+        assert_eq!(decay_at(&timeline, 9), 0.9);
+        assert_eq!(decay_at(&timeline, 10), 0.5);
+    }
+
+    #[test]
+    fn easings_run_from_0_to_1() {
+        for easing in [
+            Easing::Linear,
+            Easing::EaseIn,
+            Easing::EaseOut,
+            Easing::EaseInOut,
+        ] {
+            assert_eq!(easing.apply(0.0), 0.0, "{:?}", easing);
+            assert!((easing.apply(1.0) - 1.0).abs() < 1e-6, "{:?}", easing);
+            assert!(easing.apply(0.25) < easing.apply(0.75), "{:?}", easing);
+        }
+        assert!((Easing::EaseInOut.apply(0.5) - 0.5).abs() < 1e-6);
+        assert_eq!(Easing::Step.apply(0.99), 0.0);
+    }
+
+    #[test]
+    fn bad_keyframes_are_rejected() {
+        let missing_frame = "[[keyframe]]\nparam = \"world.decay\"\nvalue = 0.5\n";
+        assert!(Timeline::parse(missing_frame).is_err());
+        let unknown = "[[keyframe]]\nframe = 0\nparam = \"world.decay.x\"\nvalue = 0.5\n";
+        assert!(Timeline::parse(unknown).is_err());
+        assert!(Timeline::parse("fps = 30").is_err());
+    }
+}
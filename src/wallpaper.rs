@@ -0,0 +1,194 @@
+//! Headless wallpapers: a world the size of a screen, run for a number of frames and saved as a
+//! single image. A batch renders variations from consecutive seeds, optionally with the species
+//! parameters jittered around the preset. Sizes past what the GPU holds at once, such as prints,
+//! are rendered in tiles, see `tiled`.
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::food::{self, FoodSource};
+use crate::params::{MoveParams, SimParams};
+use crate::presets::{self, Preset};
+use crate::shaders::{ShaderDir, ShaderLoader};
+use crate::simulation::{self, Simulation};
+use crate::sizing::SimSize;
+use crate::tiled::{self, Tiling};
+use crate::{NUM_SLIMES, WORLD_SIZE};
+
+const WALLPAPER_DIR: &str = "wallpapers";
+const DELTA_TIME: f32 = 1.0 / 60.0;
+
+/// Screen sizes by name, in pixels.
+pub const DEVICES: &[(&str, (u32, u32))] = &[
+    ("phone", (1284, 2778)),
+    ("monitor", (3840, 2160)),
+    ("monitor-half", (1920, 2160)),
+    ("1080p", (1920, 1080)),
+    ("1440p", (2560, 1440)),
+];
+
+/// Parses `--device`, either a name from `DEVICES` or a size such as `2560x1600`.
+pub fn device_size(arg: &str) -> Result<(u32, u32), String> {
+    if let Some(&(_, size)) = DEVICES.iter().find(|(name, _)| *name == arg) {
+        return Ok(size);
+    }
+    let size = arg
+        .split_once('x')
+        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)));
+    match size {
+        Some((width, height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => {
+            let names: Vec<&str> = DEVICES.iter().map(|(name, _)| *name).collect();
+            Err(format!(
+                "expected one of {} or a size such as 2560x1600, got {}",
+                names.join(", "),
+                arg
+            ))
+        }
+    }
+}
+
+pub struct WallpaperOptions {
+    /// Device name or size, see `device_size`.
+    pub device: String,
+    /// By default the window's slime density, scaled to the wallpaper's area.
+    pub slimes: Option<u32>,
+    pub preset: Option<String>,
+    /// Seed of the first wallpaper, random if missing.
+    pub seed: Option<u64>,
+    /// Frames to run before saving.
+    pub frames: u64,
+    /// Wallpapers to render, each from the next seed.
+    pub count: u32,
+    /// Relative jitter of every species parameter in a batch, 0 keeps the preset as it is.
+    pub vary: f32,
+    /// Renders in tiles of this many pixels instead of all at once.
+    pub tile: Option<u32>,
+    /// Pixels around each tile simulated with it, see `Tiling`.
+    pub halo: u32,
+}
+
+/// Multiplies every parameter by a random factor between `1 - vary` and `1 + vary`.
+fn jitter(params: &MoveParams, vary: f32, rng: &mut impl Rng) -> MoveParams {
+    let mut scale = |value: f32| value * rng.gen_range(1.0 - vary..=1.0 + vary);
+    MoveParams::new(
+        scale(params.move_speed),
+        scale(params.turn_speed),
+        scale(params.sensor_distance),
+        scale(params.sensor_angle),
+        scale(params.deposit_amount),
+    )
+}
+
+/// Renders `options.count` wallpapers to `wallpapers/<device>-<seed>.png`, each with the
+/// parameters it was made with next to it as `.toml`.
+pub fn run(
+    options: &WallpaperOptions,
+    shader_dir: ShaderDir,
+    food_path: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    let world = device_size(&options.device)?;
+    let area = world.0 as u64 * world.1 as u64;
+    let size = SimSize {
+        world,
+        num_slimes: options.slimes.unwrap_or_else(|| {
+            (NUM_SLIMES as u64 * area / (WORLD_SIZE.0 as u64 * WORLD_SIZE.1 as u64)).max(1) as u32
+        }),
+    };
+    let tiling = options
+        .tile
+        .map(|tile| Tiling::new(world, tile, options.halo, size.num_slimes as u64));
+    let first_seed = options.seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64
+    });
+    let mut base = SimParams::default();
+    if let Some(name) = &options.preset {
+        presets::load(name)
+            .map_err(|e| format!("error loading preset {}: {}", name, e))?
+            .apply(&mut base);
+    }
+    let food_sources: Vec<FoodSource> = match food_path {
+        Some(_) if tiling.is_some() => return Err("tiled renders don't support food".into()),
+        Some(path) => food::load(path, size.world, base.food.radius)?,
+        None => Vec::new(),
+    };
+    let vary = options.vary.clamp(0.0, 1.0);
+
+    let device_size = tiling.as_ref().map_or(size, Tiling::sim_size);
+    let (device, queue) = pollster::block_on(simulation::headless_device(&device_size))?;
+    let shader_loader = ShaderLoader::new(shader_dir, &device);
+    std::fs::create_dir_all(WALLPAPER_DIR)?;
+    println!(
+        "Rendering {} wallpaper(s) of {}x{} with {} slimes, {} frames each",
+        options.count, world.0, world.1, size.num_slimes, options.frames
+    );
+    if let Some(tiling) = &tiling {
+        println!("{}", tiling.describe(&base));
+        if base.lifecycle.enabled != 0 {
+            println!("The lifecycle is turned off, tiled renders don't support it");
+        }
+    }
+
+    for index in 0..options.count as u64 {
+        let seed = first_seed.wrapping_add(index);
+        let mut params = base;
+        if vary > 0.0 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            for move_params in params.species.species.iter_mut() {
+                *move_params = jitter(move_params, vary, &mut rng);
+            }
+        }
+        let name = PathBuf::from(WALLPAPER_DIR).join(format!("{}-{}", options.device, seed));
+        match &tiling {
+            Some(tiling) => tiled::render(
+                &device,
+                &queue,
+                &shader_loader,
+                tiling,
+                &params,
+                seed,
+                options.frames,
+                &name.with_extension("png"),
+            )?,
+            None => {
+                let mut sim = Simulation::new(
+                    &device,
+                    &shader_loader,
+                    size,
+                    &params,
+                    food_sources.clone(),
+                    seed,
+                );
+                for _ in 0..options.frames {
+                    sim.step(&device, &queue, DELTA_TIME, &params);
+                }
+                image::save_buffer_with_format(
+                    name.with_extension("png"),
+                    &sim.capture(&device, &queue),
+                    world.0,
+                    world.1,
+                    image::ColorType::Rgba8,
+                    image::ImageFormat::Png,
+                )?;
+            }
+        }
+        std::fs::write(
+            name.with_extension("toml"),
+            toml::to_string(&Preset::from_params(&params))?,
+        )?;
+        println!(
+            "{:>3}/{}  Wallpaper Saved: {}",
+            index + 1,
+            options.count,
+            name.with_extension("png").display()
+        );
+    }
+    Ok(())
+}